@@ -0,0 +1,344 @@
+//! Strips terminal escape sequences from input lines, so that colored output from `rg`, `git`,
+//! `ls` or cargo can be matched like plain text.
+//!
+//! OSC 8 hyperlinks, as printed by `ls --hyperlink` or `rg --hyperlink-format`, are kept aside:
+//! their target often names a file more precisely than the text they wrap. So are colors, to show
+//! the line as it was printed.
+
+use ratatui::style::{Color, Modifier, Style};
+use std::ops::Range;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+/// The 8-bit form of `ESC [`.
+const CSI: char = '\u{9b}';
+/// The 8-bit CSI as a byte of its own in input that isn't UTF-8, as [`crate::os_str::decode`]
+/// escapes it.
+const RAW_CSI: char = '\u{10ff9b}';
+
+/// A line with its escape sequences removed.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Sanitized {
+    pub text: String,
+    pub links: Vec<Hyperlink>,
+    /// Byte ranges of `text` that were printed with colors or other attributes.
+    pub styles: Vec<(Range<usize>, Style)>,
+}
+
+/// An OSC 8 hyperlink, with the byte range of its text in [`Sanitized::text`].
+#[derive(Debug, Eq, PartialEq)]
+pub struct Hyperlink {
+    pub uri: String,
+    pub span: Range<usize>,
+}
+
+impl Hyperlink {
+    /// The local path a `file://` link points to.
+    pub fn file_path(&self) -> Option<String> {
+        let rest = self.uri.strip_prefix("file://")?;
+        // Skip the host, which `ls` and `rg` fill in with the hostname.
+        let path = &rest[rest.find('/')?..];
+        // file:///C:/a.txt on Windows.
+        let path = match path.as_bytes() {
+            [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => &path[1..],
+            _ => path,
+        };
+        Some(percent_decode(path))
+    }
+}
+
+pub fn sanitize(line: &str) -> Sanitized {
+    let mut sanitized = Sanitized {
+        text: String::with_capacity(line.len()),
+        ..Sanitized::default()
+    };
+    let mut open_link: Option<(String, usize)> = None;
+    let mut style = (Style::default(), 0);
+    let mut rest = line;
+    while let Some(pos) = rest.find([ESC, CSI, RAW_CSI]) {
+        sanitized.text.push_str(&rest[..pos]);
+        let (len, sequence) = escape_sequence(&rest[pos..]);
+        match sequence {
+            // OSC 8 ; params ; URI opens a link, and an empty URI closes it.
+            Sequence::Osc(osc) => {
+                if let Some((_, uri)) = osc.strip_prefix("8;").and_then(|p| p.split_once(';')) {
+                    if let Some((uri, start)) = open_link.take() {
+                        sanitized.push_link(uri, start);
+                    }
+                    if !uri.is_empty() {
+                        open_link = Some((uri.to_string(), sanitized.text.len()));
+                    }
+                }
+            }
+            Sequence::Sgr(params) => {
+                let new_style = apply_sgr(style.0, params);
+                if new_style != style.0 {
+                    sanitized.push_style(style.0, style.1);
+                    style = (new_style, sanitized.text.len());
+                }
+            }
+            Sequence::Other => {}
+        }
+        rest = &rest[pos + len..];
+    }
+    sanitized.text.push_str(rest);
+    if let Some((uri, start)) = open_link {
+        sanitized.push_link(uri, start);
+    }
+    sanitized.push_style(style.0, style.1);
+    sanitized
+}
+
+impl Sanitized {
+    fn push_link(&mut self, uri: String, start: usize) {
+        if start < self.text.len() {
+            self.links.push(Hyperlink {
+                uri,
+                span: start..self.text.len(),
+            });
+        }
+    }
+
+    fn push_style(&mut self, style: Style, start: usize) {
+        if start < self.text.len() && style != Style::default() {
+            self.styles.push((start..self.text.len(), style));
+        }
+    }
+}
+
+enum Sequence<'a> {
+    /// An operating system command, with its payload.
+    Osc(&'a str),
+    /// Select Graphic Rendition, with its parameters.
+    Sgr(&'a str),
+    Other,
+}
+
+/// Measures the escape sequence at the start of `s`, returning its length in bytes and what it is.
+fn escape_sequence(s: &str) -> (usize, Sequence<'_>) {
+    let bytes = s.as_bytes();
+    if let Some(csi) = s.chars().next().filter(|&c| c == CSI || c == RAW_CSI) {
+        return control_sequence(s, csi.len_utf8());
+    }
+    match bytes.get(1) {
+        Some(b'[') => control_sequence(s, 2),
+        // OSC, and DCS, SOS, PM and APC which are terminated the same way.
+        Some(b']' | b'P' | b'X' | b'^' | b'_') => {
+            let body = &s[2..];
+            match body.find([BEL, ESC]) {
+                Some(end) => {
+                    let terminator = if body[end..].starts_with("\x1b\\") {
+                        2
+                    } else {
+                        1
+                    };
+                    let sequence = if bytes[1] == b']' {
+                        Sequence::Osc(&body[..end])
+                    } else {
+                        Sequence::Other
+                    };
+                    (2 + end + terminator, sequence)
+                }
+                None => (s.len(), Sequence::Other),
+            }
+        }
+        // Two-character sequences, possibly with intermediate bytes, e.g. ESC ( B.
+        Some(_) => {
+            let end = 1 + bytes[1..]
+                .iter()
+                .take_while(|b| (0x20..=0x2f).contains(*b))
+                .count();
+            // The final byte, unless it starts a character of its own that isn't part of it.
+            match bytes.get(end) {
+                Some(b) if b.is_ascii() => (end + 1, Sequence::Other),
+                _ => (end, Sequence::Other),
+            }
+        }
+        None => (1, Sequence::Other),
+    }
+}
+
+/// Measures the control sequence at the start of `s`, whose parameters start at `start`.
+fn control_sequence(s: &str, start: usize) -> (usize, Sequence<'_>) {
+    let params = s.as_bytes()[start..]
+        .iter()
+        .take_while(|b| (0x20..=0x3f).contains(*b))
+        .count();
+    let end = start + params;
+    match s.as_bytes().get(end) {
+        Some(b'm') => (end + 1, Sequence::Sgr(&s[start..end])),
+        Some(0x40..=0x7e) => (end + 1, Sequence::Other),
+        _ => (end, Sequence::Other),
+    }
+}
+
+/// Applies the SGR parameters `params`, e.g. `1;38;5;208`, to `style`.
+fn apply_sgr(mut style: Style, params: &str) -> Style {
+    let mut codes = params
+        .split([';', ':'])
+        // An empty parameter means 0, and unknown ones are skipped.
+        .map(|code| match code {
+            "" => 0,
+            code => code.parse().unwrap_or(u8::MAX),
+        });
+    while let Some(code) = codes.next() {
+        style = match code {
+            0 => Style::default(),
+            1 => style.add_modifier(Modifier::BOLD),
+            2 => style.add_modifier(Modifier::DIM),
+            3 => style.add_modifier(Modifier::ITALIC),
+            4 => style.add_modifier(Modifier::UNDERLINED),
+            7 => style.add_modifier(Modifier::REVERSED),
+            9 => style.add_modifier(Modifier::CROSSED_OUT),
+            22 => style.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => style.remove_modifier(Modifier::ITALIC),
+            24 => style.remove_modifier(Modifier::UNDERLINED),
+            27 => style.remove_modifier(Modifier::REVERSED),
+            29 => style.remove_modifier(Modifier::CROSSED_OUT),
+            30..=37 => style.fg(Color::Indexed(code - 30)),
+            90..=97 => style.fg(Color::Indexed(code - 90 + 8)),
+            40..=47 => style.bg(Color::Indexed(code - 40)),
+            100..=107 => style.bg(Color::Indexed(code - 100 + 8)),
+            38 => match extended_color(&mut codes) {
+                Some(color) => style.fg(color),
+                None => style,
+            },
+            48 => match extended_color(&mut codes) {
+                Some(color) => style.bg(color),
+                None => style,
+            },
+            39 => Style { fg: None, ..style },
+            49 => Style { bg: None, ..style },
+            _ => style,
+        };
+    }
+    // `remove_modifier` remembers what it removed, which only matters when patching.
+    Style {
+        sub_modifier: Modifier::empty(),
+        ..style
+    }
+}
+
+/// Reads the rest of a 256-color (`5;n`) or true color (`2;r;g;b`) parameter.
+fn extended_color(codes: &mut impl Iterator<Item = u8>) -> Option<Color> {
+    match codes.next()? {
+        5 => Some(Color::Indexed(codes.next()?)),
+        2 => Some(Color::Rgb(codes.next()?, codes.next()?, codes.next()?)),
+        _ => None,
+    }
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ansi::{Hyperlink, sanitize};
+    use crate::os_str;
+    use ratatui::style::{Color, Modifier, Style};
+
+    #[test]
+    fn strips_colors() {
+        // rg --color=always
+        assert_eq!(
+            sanitize("\x1b[0m\x1b[35msrc/main.rs\x1b[0m:\x1b[0m\x1b[32m12\x1b[0m:fn main() {").text,
+            "src/main.rs:12:fn main() {"
+        );
+        // ls --color, with the 8-bit CSI and a charset selection thrown in
+        assert_eq!(
+            sanitize("\u{9b}01;34mdocs\u{9b}0m \x1b(Bnotes.txt").text,
+            "docs notes.txt"
+        );
+    }
+
+    #[test]
+    fn strips_the_8_bit_csi_outside_of_utf8() {
+        let line = os_str::decode(b"\x9b01;34mdocs\x9b0m/caf\xe9.txt");
+        assert_eq!(
+            os_str::display(&sanitize(&line).text),
+            "docs/caf\u{fffd}.txt"
+        );
+    }
+
+    #[test]
+    fn extracts_hyperlinks() {
+        let sanitized = sanitize(
+            "\x1b]8;;file://host/home/me/My%20Docs/a.txt\x1b\\a.txt\x1b]8;;\x1b\\ and \x1b]8;id=1;https://example.com\x07site",
+        );
+        assert_eq!(sanitized.text, "a.txt and site");
+        assert_eq!(
+            sanitized.links,
+            vec![
+                Hyperlink {
+                    uri: String::from("file://host/home/me/My%20Docs/a.txt"),
+                    span: 0..5,
+                },
+                Hyperlink {
+                    uri: String::from("https://example.com"),
+                    span: 10..14,
+                },
+            ]
+        );
+        assert_eq!(
+            sanitized.links[0].file_path().as_deref(),
+            Some("/home/me/My Docs/a.txt")
+        );
+        assert_eq!(sanitized.links[1].file_path(), None);
+    }
+
+    #[test]
+    fn keeps_colors() {
+        // rg --color=always, then a bold 256-color word and a true color background
+        let sanitized = sanitize(
+            "\x1b[0m\x1b[35ma.rs\x1b[0m:\x1b[1;38;5;208mhot\x1b[22m \x1b[48;2;1;2;3mbg\x1b[m.",
+        );
+        assert_eq!(sanitized.text, "a.rs:hot bg.");
+        assert_eq!(
+            sanitized.styles,
+            vec![
+                (0..4, Style::new().fg(Color::Indexed(5))),
+                (
+                    5..8,
+                    Style::new()
+                        .fg(Color::Indexed(208))
+                        .add_modifier(Modifier::BOLD)
+                ),
+                (8..9, Style::new().fg(Color::Indexed(208))),
+                (
+                    9..11,
+                    Style::new().fg(Color::Indexed(208)).bg(Color::Rgb(1, 2, 3))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn tolerates_truncated_sequences() {
+        assert_eq!(sanitize("a.rs\x1b[3").text, "a.rs");
+        assert_eq!(sanitize("a.rs\x1b]8;;file:///a").text, "a.rs");
+        assert_eq!(sanitize("a.rs\x1b").text, "a.rs");
+    }
+
+    #[test]
+    fn keeps_characters_after_a_stray_escape() {
+        assert_eq!(sanitize("a \x1bé b").text, "a é b");
+        assert_eq!(sanitize("a \x1b(é b").text, "a é b");
+    }
+}
@@ -0,0 +1,202 @@
+//! Runs a user-provided shell command on the selection instead of opening an editor.
+//!
+//! Placeholders in the command are substituted with the selection:
+//! - `$F` expands to all selected paths, also in commands that run once per path. Variables that
+//!   only start with it, such as `$FOO`, are left to the shell.
+//! - `{}` expands to a single path, and makes the command run once per selected path.
+//! - `{line}` expands to that path's line number (empty if unknown), likewise per path.
+//!
+//! Without any placeholder, the selected paths are appended to the command.
+
+use crate::matcher::MatchResult;
+use crate::os_str;
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::process::{Command, ExitStatus};
+
+lazy_static! {
+    // $F, but not the start of a longer variable name such as $FOO, then {} and {line}.
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\$F\b|\{\}|\{line\}").unwrap();
+}
+
+/// Expands `template` into the shell command lines to run, in order.
+pub fn expand(template: &str, selections: &[MatchResult]) -> Vec<String> {
+    let paths = selections
+        .iter()
+        .map(|s| shell_quote(&s.expanded_path()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let is_per_file = PLACEHOLDER_REGEX
+        .find_iter(template)
+        .any(|m| m.as_str() != "$F");
+    if is_per_file {
+        return selections
+            .iter()
+            .map(|s| substitute(template, &paths, Some(s)))
+            .collect();
+    }
+    if PLACEHOLDER_REGEX.is_match(template) {
+        vec![substitute(template, &paths, None)]
+    } else {
+        vec![format!("{template} {paths}")]
+    }
+}
+
+/// Fills in the placeholders of `template` in a single pass, so that paths that look like one are
+/// left alone. `{}` and `{line}` stand for `selection`.
+fn substitute(template: &str, paths: &str, selection: Option<&MatchResult>) -> String {
+    PLACEHOLDER_REGEX
+        .replace_all(template, |captures: &Captures| {
+            match (&captures[0], selection) {
+                ("{}", Some(s)) => shell_quote(&s.expanded_path()),
+                ("{line}", Some(s)) => s.line_number.map(|l| l.to_string()).unwrap_or_default(),
+                _ => paths.to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Runs every expanded command line through the shell, stopping at the first one that can't be
+/// spawned. Returns each command line, as displayed, along with its exit status.
+pub fn run(template: &str, selections: &[MatchResult]) -> io::Result<Vec<(String, ExitStatus)>> {
+    let mut statuses = vec![];
+    for command_line in expand(template, selections) {
+        let mut command = shell_command(&os_str::to_os_string(&command_line));
+        // Same as for the editor, the command gets the terminal rather than the input pipe.
+        if let Ok(tty) = File::open("/dev/tty") {
+            command.stdin(tty);
+        }
+        let status = command.status()?;
+        statuses.push((os_str::display(&command_line).into_owned(), status));
+    }
+    Ok(statuses)
+}
+
+#[cfg(not(windows))]
+fn shell_command(command_line: &OsStr) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(command_line);
+    command
+}
+
+#[cfg(windows)]
+fn shell_command(command_line: &OsStr) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    let mut command = Command::new("cmd");
+    // Passed as is, as cmd doesn't undo the quoting other programs' arguments get.
+    command.arg("/C").raw_arg(command_line);
+    command
+}
+
+/// Quotes `s` for the shell commands are run with, leaving it alone if there's nothing to escape.
+fn shell_quote(s: &str) -> String {
+    if cfg!(windows) {
+        cmd_quote(s)
+    } else {
+        posix_quote(s)
+    }
+}
+
+/// Quotes `s` for a POSIX shell.
+fn posix_quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-~+,:@%".contains(c))
+    {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Quotes `s` for `cmd.exe`, where `&`, `|`, `<`, `>`, `^` and spaces lose their meaning between
+/// double quotes. Windows paths can't contain double quotes themselves.
+fn cmd_quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "\\/._-~+,:@".contains(c))
+    {
+        return s.to_string();
+    }
+    format!("\"{s}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::{cmd_quote, expand};
+    use crate::matcher::MatchResult;
+
+    fn selections() -> Vec<MatchResult> {
+        vec![
+            MatchResult {
+                path: String::from("src/a.rs"),
+                line_number: Some(3),
+                ..Default::default()
+            },
+            MatchResult {
+                path: String::from("My Docs/it's.txt"),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn appends_paths_without_placeholders() {
+        assert_eq!(
+            expand("git add", &selections()),
+            vec![r"git add src/a.rs 'My Docs/it'\''s.txt'"]
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn substitutes_all_files() {
+        assert_eq!(
+            expand("tar czf out.tgz $F && echo done", &selections()),
+            vec![r"tar czf out.tgz src/a.rs 'My Docs/it'\''s.txt' && echo done"]
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn runs_once_per_file() {
+        assert_eq!(
+            expand("echo {}:{line}", &selections()),
+            vec!["echo src/a.rs:3", r"echo 'My Docs/it'\''s.txt':"]
+        );
+        assert_eq!(
+            expand("diff {} $F", &selections()[..1]),
+            vec!["diff src/a.rs src/a.rs"]
+        );
+    }
+
+    #[test]
+    fn leaves_other_variables_alone() {
+        let selections = &selections()[..1];
+        assert_eq!(
+            expand("echo $FOO $F_X ${F} $F.", selections),
+            vec!["echo $FOO $F_X ${F} src/a.rs."]
+        );
+        assert_eq!(expand("echo $FOO", selections), vec!["echo $FOO src/a.rs"]);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn leaves_placeholders_in_paths_alone() {
+        let selections = [MatchResult {
+            path: String::from("$F{line}.txt"),
+            ..Default::default()
+        }];
+        assert_eq!(expand("echo {}", &selections), vec!["echo '$F{line}.txt'"]);
+    }
+
+    #[test]
+    fn quotes_for_cmd() {
+        assert_eq!(cmd_quote(r"src\a.rs"), r"src\a.rs");
+        assert_eq!(cmd_quote(r"C:\My Docs\a&b.txt"), r#""C:\My Docs\a&b.txt""#);
+    }
+}
@@ -0,0 +1,316 @@
+//! Loads the user configuration from `$XDG_CONFIG_HOME/rfp/config.toml`, or the file passed with
+//! `--config`.
+//!
+//! ```toml
+//! output = "print"              # editor, print, print0 or json
+//! with_position = true
+//! per_line = true
+//! ignore = ["target/**", "*.lock"]
+//! roots = ["~/src/app"]         # where to look for paths that aren't relative to the cwd
+//! strip_prefixes = ['^/ci/build/[^/]+/']
+//! matchers = ['^(?P<path>\S+) \(line (?P<line>\d+)\)']
+//!
+//! [editor]
+//! command = "emacsclient -t"
+//! quickfix = true                # open Vim selections as a quickfix list
+//!
+//! [editor.templates]
+//! kak = ["+{line}", "{file}"]
+//!
+//! [keys]
+//! down = ["j", "ctrl-n"]
+//! quit = "q"
+//!
+//! [colors]
+//! cursor = "blue"
+//! match = "#ff8800"
+//! ```
+//!
+//! Anything invalid is reported with its location in the file instead of being ignored.
+
+use crate::editor::Editor;
+use crate::expand::expand_path;
+use crate::keymap::{Action, KeyBinding, Keymap};
+use crate::output::OutputFormat;
+use crate::tui::Theme;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+/// The configuration file, validated.
+#[derive(Debug, Default)]
+pub struct FileConfig {
+    pub editor: Option<Editor>,
+    /// Per-editor argument templates, keyed by program name. See [`Editor::template`].
+    pub editor_templates: HashMap<String, Vec<String>>,
+    /// See [`Editor::quickfix`].
+    pub editor_quickfix: bool,
+    /// Candidates matching any of these globs are dropped.
+    pub ignore: GlobSet,
+    pub keymap: Keymap,
+    /// Extra regexes tried before the built-in ones, with a `path` group and optional `line` and
+    /// `column` groups.
+    pub matchers: Vec<Regex>,
+    /// `None` opens the editor.
+    pub print_format: Option<OutputFormat>,
+    pub theme: Theme,
+    pub with_position: bool,
+    /// Keep a candidate per line instead of per path.
+    pub per_line: bool,
+    /// Where paths that aren't relative to the cwd are looked up, after the git worktree root.
+    pub roots: Vec<PathBuf>,
+    /// Prefixes stripped from absolute paths that don't exist, to look them up under the roots.
+    pub strip_prefixes: Vec<Regex>,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    path: PathBuf,
+    /// 1-based line and column.
+    location: Option<(usize, usize)>,
+    message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(
+                f,
+                "{}:{line}:{column}: {}",
+                self.path.display(),
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    output: Option<toml::Spanned<String>>,
+    with_position: bool,
+    per_line: bool,
+    ignore: Vec<toml::Spanned<String>>,
+    matchers: Vec<toml::Spanned<String>>,
+    roots: Vec<String>,
+    strip_prefixes: Vec<toml::Spanned<String>>,
+    editor: RawEditor,
+    keys: BTreeMap<String, toml::Spanned<RawKeys>>,
+    colors: BTreeMap<String, toml::Spanned<String>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawEditor {
+    command: Option<toml::Spanned<String>>,
+    templates: BTreeMap<String, toml::Spanned<Vec<String>>>,
+    quickfix: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Where the configuration is read from when `--config` isn't given.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("rfp").join("config.toml"))
+}
+
+pub fn load(path: &Path) -> Result<FileConfig, ConfigError> {
+    let source = fs::read_to_string(path).map_err(|e| ConfigError {
+        path: path.to_path_buf(),
+        location: None,
+        message: e.to_string(),
+    })?;
+    parse(&source, path)
+}
+
+/// Parses and validates `source`, the contents of the file at `path`.
+pub fn parse(source: &str, path: &Path) -> Result<FileConfig, ConfigError> {
+    let error = |span: Option<Range<usize>>, message: String| ConfigError {
+        path: path.to_path_buf(),
+        location: span.map(|s| location(source, s.start)),
+        message,
+    };
+    let raw: RawConfig =
+        toml::from_str(source).map_err(|e| error(e.span(), e.message().to_string()))?;
+
+    let mut config = FileConfig {
+        with_position: raw.with_position,
+        per_line: raw.per_line,
+        editor_quickfix: raw.editor.quickfix,
+        ..FileConfig::default()
+    };
+    if let Some(output) = raw.output {
+        config.print_format = match output.get_ref().as_str() {
+            "editor" => None,
+            "print" => Some(OutputFormat::Newline),
+            "print0" => Some(OutputFormat::Null),
+            "json" => Some(OutputFormat::JsonLines),
+            other => {
+                return Err(error(
+                    Some(output.span()),
+                    format!("Unknown output `{other}`, expected editor, print, print0 or json"),
+                ));
+            }
+        };
+    }
+
+    let mut ignore = GlobSetBuilder::new();
+    for pattern in raw.ignore {
+        let glob =
+            Glob::new(pattern.get_ref()).map_err(|e| error(Some(pattern.span()), e.to_string()))?;
+        ignore.add(glob);
+    }
+    config.ignore = ignore.build().map_err(|e| error(None, e.to_string()))?;
+
+    for pattern in raw.matchers {
+        let regex = Regex::new(pattern.get_ref())
+            .map_err(|e| error(Some(pattern.span()), e.to_string()))?;
+        if !regex.capture_names().any(|n| n == Some("path")) {
+            return Err(error(
+                Some(pattern.span()),
+                "Matcher has no `path` group".to_string(),
+            ));
+        }
+        config.matchers.push(regex);
+    }
+
+    config.roots = raw
+        .roots
+        .iter()
+        .map(|root| PathBuf::from(expand_path(root).as_ref()))
+        .collect();
+    for prefix in raw.strip_prefixes {
+        let regex =
+            Regex::new(prefix.get_ref()).map_err(|e| error(Some(prefix.span()), e.to_string()))?;
+        config.strip_prefixes.push(regex);
+    }
+
+    if let Some(command) = raw.editor.command {
+        config.editor =
+            Some(Editor::parse(command.get_ref()).map_err(|e| error(Some(command.span()), e))?);
+    }
+    for (program, template) in raw.editor.templates {
+        if !template.get_ref().iter().any(|arg| arg.contains("{file}")) {
+            return Err(error(
+                Some(template.span()),
+                format!("Template for `{program}` has no {{file}} placeholder"),
+            ));
+        }
+        config
+            .editor_templates
+            .insert(program, template.into_inner());
+    }
+
+    for (name, keys) in raw.keys {
+        let span = keys.span();
+        let action = Action::from_name(&name)
+            .ok_or_else(|| error(Some(span.clone()), format!("Unknown action `{name}`")))?;
+        let keys = match keys.into_inner() {
+            RawKeys::One(key) => vec![key],
+            RawKeys::Many(keys) => keys,
+        };
+        let keys = keys
+            .iter()
+            .map(|k| KeyBinding::parse(k))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| error(Some(span.clone()), e))?;
+        config.keymap.bind(action, keys);
+    }
+
+    for (name, color) in raw.colors {
+        config
+            .theme
+            .set(&name, color.get_ref())
+            .map_err(|e| error(Some(color.span()), e))?;
+    }
+    Ok(config)
+}
+
+/// Converts a byte offset into `source` to a 1-based line and column.
+fn location(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::parse;
+    use crate::keymap::Action;
+    use crate::output::OutputFormat;
+    use std::path::Path;
+
+    #[test]
+    fn parses_config() {
+        let config = parse(
+            r##"
+output = "json"
+ignore = ["target/**"]
+matchers = ['^(?P<path>\S+) \(line (?P<line>\d+)\)']
+roots = ["/opt/src"]
+strip_prefixes = ['^/ci/[^/]+/']
+
+[editor]
+command = "emacsclient -t"
+quickfix = true
+
+[editor.templates]
+kak = ["+{line}", "{file}"]
+
+[keys]
+down = ["j", "ctrl-n"]
+
+[colors]
+match = "#ff8800"
+"##,
+            Path::new("config.toml"),
+        )
+        .unwrap();
+        assert_eq!(config.print_format, Some(OutputFormat::JsonLines));
+        assert!(config.ignore.is_match("target/debug/rfp"));
+        assert_eq!(config.matchers.len(), 1);
+        assert_eq!(config.roots, vec![Path::new("/opt/src")]);
+        assert!(config.strip_prefixes[0].is_match("/ci/job/src/a.rs"));
+        assert_eq!(config.editor.unwrap().program, "emacsclient");
+        assert!(config.editor_quickfix);
+        assert_eq!(config.editor_templates["kak"], vec!["+{line}", "{file}"]);
+        assert_eq!(config.keymap.label(Action::Down), "j/ctrl-n");
+    }
+
+    #[test]
+    fn reports_error_locations() {
+        let error = |source: &str| {
+            parse(source, Path::new("config.toml"))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("output = \"print\"\n\n[keys]\nfly = \"x\"\n"),
+            "config.toml:4:7: Unknown action `fly`"
+        );
+        assert_eq!(
+            error("[colors]\ncursor = \"blurple\"\n"),
+            "config.toml:2:10: Unknown color `blurple`"
+        );
+        assert!(error("editor = 1\n").starts_with("config.toml:1:10: "));
+        assert!(error("outptu = \"print\"\n").starts_with("config.toml:1:1: "));
+    }
+}
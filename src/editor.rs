@@ -0,0 +1,527 @@
+//! Translates a selection into editor-specific command line arguments so that files are opened at
+//! the matched line instead of the top.
+
+use crate::matcher::MatchResult;
+use crate::os_str;
+use crate::pipe::Candidate;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::process::Command;
+use tempfile::NamedTempFile;
+
+/// An editor command line such as `code -w` or `emacsclient -t`, split into the program and the
+/// arguments it always gets.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Editor {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Arguments to open each selection with, overriding the built-in ones for `program`. `{file}`
+    /// expands to the path, `{line}` to the line number and `{column}` to the column. Arguments
+    /// containing `{line}` or `{column}` are left out when that is unknown.
+    pub template: Option<Vec<String>>,
+    /// Always hand Vim and Neovim the selection as a quickfix list, rather than only when it has
+    /// several lines of a file.
+    pub quickfix: bool,
+}
+
+impl Editor {
+    /// Splits `command_line` into words the way a POSIX shell would.
+    pub fn parse(command_line: &str) -> Result<Self, String> {
+        let mut words = shell_words::split(command_line)
+            .map_err(|e| format!("Could not parse editor command `{command_line}`: {e}"))?;
+        if words.is_empty() {
+            return Err("Editor command is empty".to_string());
+        }
+        let program = words.remove(0);
+        Ok(Editor {
+            program,
+            args: words,
+            template: None,
+            quickfix: false,
+        })
+    }
+
+    /// Picks the template configured for this editor's program, if any.
+    pub fn apply_templates(&mut self, templates: &HashMap<String, Vec<String>>) {
+        self.template = templates.get(&program_name(&self.program)).cloned();
+    }
+
+    fn selection_args(&self, selections: &[Candidate], errorfile: Option<&Path>) -> Vec<String> {
+        if let Some(errorfile) = errorfile {
+            return vec![String::from("-q"), errorfile.to_string_lossy().into_owned()];
+        }
+        let results: Vec<MatchResult> = selections.iter().map(|s| s.result.clone()).collect();
+        match &self.template {
+            Some(template) => expand_template(template, &results),
+            None => build_args(&self.program, &results),
+        }
+    }
+
+    /// Where Vim reads the selection from as a quickfix list, so that `:cnext` steps through it.
+    /// That's also how several lines of a file are opened, as `+N` only positions the first file,
+    /// once.
+    fn uses_errorfile(&self, selections: &[Candidate]) -> bool {
+        let is_vim =
+            self.template.is_none() && EditorKind::from_command(&self.program) == EditorKind::Vim;
+        let mut paths = HashSet::new();
+        let has_repeated_path = !selections
+            .iter()
+            .all(|s| paths.insert(s.result.expanded_path()));
+        is_vim && (self.quickfix || has_repeated_path)
+    }
+
+    /// The full command to open `selections` with, along with the files it needs.
+    pub fn command(&self, selections: &[Candidate]) -> io::Result<EditorCommand> {
+        let errorfile = if self.uses_errorfile(selections) {
+            // A fresh file with a random name, so that nobody can have put a link in its place.
+            let mut file = tempfile::Builder::new()
+                .prefix("rfp-")
+                .suffix(".err")
+                .tempfile()?;
+            write_errorfile(&mut file, selections)?;
+            Some(file)
+        } else {
+            None
+        };
+        let selection_args =
+            self.selection_args(selections, errorfile.as_ref().map(NamedTempFile::path));
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .args(selection_args.iter().map(|arg| os_str::to_os_string(arg)));
+
+        let mut words = vec![self.program.clone()];
+        words.extend(self.args.iter().cloned());
+        words.extend(selection_args);
+        Ok(EditorCommand {
+            command,
+            command_line: os_str::display(&shell_words::join(words)).into_owned(),
+            _errorfile: errorfile,
+        })
+    }
+}
+
+/// An editor command, along with the errorfile written for it, which is removed when dropped.
+pub struct EditorCommand {
+    command: Command,
+    command_line: String,
+    _errorfile: Option<NamedTempFile>,
+}
+
+impl EditorCommand {
+    /// The command line that is run, quoted for display.
+    pub fn command_line(&self) -> &str {
+        &self.command_line
+    }
+}
+
+impl Deref for EditorCommand {
+    type Target = Command;
+
+    fn deref(&self) -> &Command {
+        &self.command
+    }
+}
+
+impl DerefMut for EditorCommand {
+    fn deref_mut(&mut self) -> &mut Command {
+        &mut self.command
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum EditorKind {
+    Vim,
+    Emacs,
+    Nano,
+    VsCode,
+    Sublime,
+    Helix,
+    Unknown,
+}
+
+impl EditorKind {
+    fn from_command(editor: &str) -> Self {
+        match program_name(editor).as_str() {
+            "vi" | "vim" | "nvim" | "gvim" | "mvim" => EditorKind::Vim,
+            "emacs" | "emacsclient" => EditorKind::Emacs,
+            "nano" => EditorKind::Nano,
+            "code" | "code-insiders" | "codium" => EditorKind::VsCode,
+            "subl" => EditorKind::Sublime,
+            "hx" | "helix" => EditorKind::Helix,
+            _ => EditorKind::Unknown,
+        }
+    }
+}
+
+/// The file name of `program`, without the `.exe` of Windows, e.g. `nvim` for `/opt/nvim.exe`.
+fn program_name(program: &str) -> String {
+    let name = Path::new(program)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match name.len().checked_sub(".exe".len()) {
+        Some(stem)
+            if name
+                .get(stem..)
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(".exe")) =>
+        {
+            name[..stem].to_string()
+        }
+        _ => name,
+    }
+}
+
+/// Builds the arguments to pass to `editor` in order to open every selection, positioned at its
+/// line and column whenever the editor supports it. Editors we don't know about get bare paths.
+pub fn build_args(editor: &str, selections: &[MatchResult]) -> Vec<String> {
+    let mut args = vec![];
+    match EditorKind::from_command(editor) {
+        // Vim only honors `+N` for the first file in the argument list.
+        EditorKind::Vim => {
+            if let Some(first) = selections.first() {
+                match (first.line_number, first.column) {
+                    (Some(line), Some(column)) => {
+                        args.push(format!("+call cursor({line},{column})"))
+                    }
+                    (Some(line), None) => args.push(format!("+{line}")),
+                    _ => {}
+                }
+            }
+            args.extend(selections.iter().map(|s| s.expanded_path().into_owned()));
+        }
+        kind @ (EditorKind::Emacs | EditorKind::Nano) => {
+            let separator = if kind == EditorKind::Emacs { ':' } else { ',' };
+            for selection in selections {
+                match (selection.line_number, selection.column) {
+                    (Some(line), Some(column)) => args.push(format!("+{line}{separator}{column}")),
+                    (Some(line), None) => args.push(format!("+{line}")),
+                    _ => {}
+                }
+                args.push(selection.expanded_path().into_owned());
+            }
+        }
+        EditorKind::VsCode => {
+            if selections.iter().any(|s| s.line_number.is_some()) {
+                args.push("-g".to_string());
+            }
+            args.extend(selections.iter().map(path_with_position));
+        }
+        EditorKind::Sublime | EditorKind::Helix => {
+            args.extend(selections.iter().map(path_with_position));
+        }
+        EditorKind::Unknown => {
+            args.extend(selections.iter().map(|s| s.expanded_path().into_owned()));
+        }
+    }
+    args
+}
+
+fn expand_template(template: &[String], selections: &[MatchResult]) -> Vec<String> {
+    let mut args = vec![];
+    for selection in selections {
+        let line = selection.line_number.map(|l| l.to_string());
+        let column = selection.column.map(|c| c.to_string());
+        for arg in template {
+            if (arg.contains("{line}") && line.is_none())
+                || (arg.contains("{column}") && column.is_none())
+            {
+                continue;
+            }
+            args.push(
+                arg.replace("{file}", &selection.expanded_path())
+                    .replace("{line}", line.as_deref().unwrap_or_default())
+                    .replace("{column}", column.as_deref().unwrap_or_default()),
+            );
+        }
+    }
+    args
+}
+
+/// Writes `selections` in a format Vim's default `errorformat` understands, `path:line:column:
+/// message`.
+fn write_errorfile(writer: &mut impl Write, selections: &[Candidate]) -> io::Result<()> {
+    for selection in selections {
+        let result = &selection.result;
+        writer.write_all(&os_str::encode(&result.expanded_path()))?;
+        write!(writer, ":{}:", result.line_number.unwrap_or(1))?;
+        if let Some(column) = result.column {
+            write!(writer, "{column}:")?;
+        }
+        writer.write_all(b" ")?;
+        writer.write_all(&os_str::encode(&quickfix_message(selection)))?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// The line `candidate` was found on, less the path and position it starts with in `rg -n` style
+/// output. Quickfix entries need a message, so it falls back to the path.
+fn quickfix_message(candidate: &Candidate) -> Cow<'_, str> {
+    let path = Cow::Borrowed(candidate.result.path.as_str());
+    let Some(source) = &candidate.source else {
+        return path;
+    };
+    let text = &source.text;
+    let message = if text[..source.span.start].trim().is_empty() {
+        text[source.span.end..].trim_start_matches(|c: char| c == ':' || c.is_ascii_digit())
+    } else {
+        text
+    };
+    match message.trim() {
+        "" => path,
+        message => Cow::Borrowed(message),
+    }
+}
+
+fn path_with_position(selection: &MatchResult) -> String {
+    match (selection.line_number, selection.column) {
+        (Some(line), Some(column)) => format!("{}:{line}:{column}", selection.expanded_path()),
+        (Some(line), None) => format!("{}:{line}", selection.expanded_path()),
+        _ => selection.expanded_path().into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::{Editor, build_args, write_errorfile};
+    use crate::matcher::MatchResult;
+    use crate::os_str;
+    use crate::pipe::{Candidate, Source};
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn selection(path: &str, line_number: Option<u32>) -> MatchResult {
+        MatchResult {
+            path: String::from(path),
+            line_number,
+            ..Default::default()
+        }
+    }
+
+    fn candidate(path: &str, line_number: Option<u32>) -> Candidate {
+        Candidate {
+            result: selection(path, line_number),
+            source: None,
+        }
+    }
+
+    fn command_line(editor: &Editor, selections: &[Candidate]) -> String {
+        editor
+            .command(selections)
+            .unwrap()
+            .command_line()
+            .to_string()
+    }
+
+    #[test]
+    fn parses_editor_with_arguments() {
+        assert_eq!(
+            Editor::parse(r#"emacsclient -t --alternate-editor="""#).unwrap(),
+            Editor {
+                program: String::from("emacsclient"),
+                args: vec![String::from("-t"), String::from("--alternate-editor=")],
+                template: None,
+                quickfix: false,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_quoted_program() {
+        assert_eq!(
+            Editor::parse(r#""/Applications/My Editor/bin/edit" -w"#)
+                .unwrap()
+                .program,
+            "/Applications/My Editor/bin/edit"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_unterminated_commands() {
+        assert!(Editor::parse("  ").is_err());
+        assert!(Editor::parse("code 'oops").is_err());
+    }
+
+    #[test]
+    fn command_line_includes_editor_arguments() {
+        assert_eq!(
+            command_line(
+                &Editor::parse("code -w").unwrap(),
+                &[candidate("a b.rs", Some(10))]
+            ),
+            "code -w -g 'a b.rs:10'"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn passes_invalid_utf8_paths_through() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = os_str::decode(b"caf\xe9.rs");
+        let editor = Editor::parse("vim").unwrap();
+        let command = editor.command(&[candidate(&path, None)]).unwrap();
+        let args: Vec<&[u8]> = command.get_args().map(|arg| arg.as_bytes()).collect();
+        assert_eq!(args, vec![b"caf\xe9.rs".as_slice()]);
+        assert_eq!(command.command_line(), "vim caf\u{fffd}.rs");
+    }
+
+    #[test]
+    fn vim_positions_the_first_file() {
+        assert_eq!(
+            build_args(
+                "/usr/bin/nvim",
+                &[selection("a.rs", Some(10)), selection("b.rs", Some(20))]
+            ),
+            vec!["+10", "a.rs", "b.rs"]
+        );
+    }
+
+    #[test]
+    fn vim_reads_several_lines_of_a_file_from_an_errorfile() {
+        let mut editor = Editor::parse("vim").unwrap();
+        let selections = [
+            candidate("a.rs", Some(10)),
+            candidate("b.rs", None),
+            candidate("a.rs", Some(20)),
+        ];
+        let command = editor.command(&selections).unwrap();
+        let args: Vec<&OsStr> = command.get_args().collect();
+        assert_eq!(args[0], "-q");
+        let errorfile = PathBuf::from(args[1]);
+        assert!(
+            fs::read_to_string(&errorfile)
+                .unwrap()
+                .starts_with("a.rs:10: ")
+        );
+        drop(command);
+        assert!(!errorfile.exists());
+
+        assert_eq!(command_line(&editor, &selections[..2]), "vim +10 a.rs b.rs");
+        assert!(!command_line(&Editor::parse("code").unwrap(), &selections).contains("-q"));
+        editor.quickfix = true;
+        assert!(command_line(&editor, &selections[..2]).starts_with("vim -q "));
+    }
+
+    #[test]
+    fn writes_quickfix_entries_with_their_lines() {
+        let source = |text: &str, span| {
+            Some(Source {
+                text: String::from(text),
+                styles: vec![],
+                span,
+            })
+        };
+        let mut grep_hit = candidate("a.rs", Some(10));
+        grep_hit.source = source("a.rs:10:  let x = 1;", 0..4);
+        let mut error = candidate("b.rs", Some(3));
+        error.result.column = Some(7);
+        error.source = source("error: oops at b.rs:3:7", 15..19);
+        let mut bare_path = candidate("c.rs", None);
+        bare_path.source = source("c.rs", 0..4);
+        let mut written = vec![];
+        write_errorfile(&mut written, &[grep_hit, error, bare_path]).unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "a.rs:10: let x = 1;\nb.rs:3:7: error: oops at b.rs:3:7\nc.rs:1: c.rs\n"
+        );
+    }
+
+    #[test]
+    fn forwards_columns_to_editors_that_take_them() {
+        let selections = [
+            MatchResult {
+                column: Some(5),
+                ..selection("a.rs", Some(10))
+            },
+            selection("b.rs", Some(20)),
+        ];
+        assert_eq!(
+            build_args("vim", &selections),
+            vec!["+call cursor(10,5)", "a.rs", "b.rs"]
+        );
+        assert_eq!(
+            build_args("emacsclient", &selections),
+            vec!["+10:5", "a.rs", "+20", "b.rs"]
+        );
+        assert_eq!(
+            build_args("nano", &selections),
+            vec!["+10,5", "a.rs", "+20", "b.rs"]
+        );
+        assert_eq!(build_args("hx", &selections), vec!["a.rs:10:5", "b.rs:20"]);
+    }
+
+    #[test]
+    fn emacs_positions_every_file() {
+        assert_eq!(
+            build_args(
+                "emacs",
+                &[selection("a.rs", Some(10)), selection("b.rs", None)]
+            ),
+            vec!["+10", "a.rs", "b.rs"]
+        );
+    }
+
+    #[test]
+    fn vscode_uses_goto() {
+        assert_eq!(
+            build_args(
+                "code",
+                &[selection("a.rs", Some(10)), selection("b.rs", None)]
+            ),
+            vec!["-g", "a.rs:10", "b.rs"]
+        );
+    }
+
+    #[test]
+    fn recognizes_windows_executables() {
+        assert_eq!(
+            build_args("code.exe", &[selection("a.rs", Some(10))]),
+            vec!["-g", "a.rs:10"]
+        );
+        assert_eq!(
+            build_args("/opt/Neovim/bin/nvim.EXE", &[selection("a.rs", Some(10))]),
+            vec!["+10", "a.rs"]
+        );
+        assert_eq!(
+            build_args(".exe", &[selection("a.rs", Some(10))]),
+            vec!["a.rs"]
+        );
+    }
+
+    #[test]
+    fn vscode_skips_goto_without_line_numbers() {
+        assert_eq!(build_args("code", &[selection("a.rs", None)]), vec!["a.rs"]);
+    }
+
+    #[test]
+    fn unknown_editor_gets_bare_paths() {
+        assert_eq!(
+            build_args("ed", &[selection("a.rs", Some(10))]),
+            vec!["a.rs"]
+        );
+    }
+
+    #[test]
+    fn configured_template_overrides_built_in_arguments() {
+        let mut editor = Editor::parse("/opt/bin/kak").unwrap();
+        let templates = HashMap::from([(
+            String::from("kak"),
+            vec![String::from("+{line}"), String::from("{file}")],
+        )]);
+        editor.apply_templates(&templates);
+        assert_eq!(
+            command_line(
+                &editor,
+                &[candidate("a.rs", Some(10)), candidate("b.rs", None)]
+            ),
+            "/opt/bin/kak +10 a.rs b.rs"
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Expands `~`, `~user`, `$VAR` and `${VAR}` in matched paths the way a shell would, so that they
+//! can be checked and opened. The list keeps showing paths as they appeared in the input.
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::env;
+
+lazy_static! {
+    static ref VAR_REGEX: Regex =
+        Regex::new(r"\$(?:\{([A-Za-z_][A-Za-z0-9_]*)\}|([A-Za-z_][A-Za-z0-9_]*))").unwrap();
+}
+
+/// Expands a leading `~` or `~user` and every `$VAR` or `${VAR}` in `path`. Anything that can't
+/// be expanded, such as an unset variable or an unknown user, is left as is.
+pub fn expand_path(path: &str) -> Cow<'_, str> {
+    let path = expand_tilde(path);
+    if !path.contains('$') {
+        return path;
+    }
+    let expanded = VAR_REGEX.replace_all(&path, |captures: &Captures| {
+        let name = captures
+            .get(1)
+            .or_else(|| captures.get(2))
+            .unwrap()
+            .as_str();
+        env::var(name).unwrap_or_else(|_| captures[0].to_string())
+    });
+    Cow::Owned(expanded.into_owned())
+}
+
+fn expand_tilde(path: &str) -> Cow<'_, str> {
+    let Some(rest) = path.strip_prefix('~') else {
+        return Cow::Borrowed(path);
+    };
+    let (user, tail) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let home = if user.is_empty() {
+        env::var("HOME").ok()
+    } else {
+        home_dir_of(user)
+    };
+    match home {
+        Some(home) => Cow::Owned(format!("{}{tail}", home.trim_end_matches('/'))),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Looks `user` up in the password database, which covers more than `/etc/passwd` on macOS and
+/// with NSS.
+#[cfg(unix)]
+fn home_dir_of(user: &str) -> Option<String> {
+    use std::ffi::{CStr, CString};
+    use std::mem::MaybeUninit;
+    use std::ptr;
+
+    let name = CString::new(user).ok()?;
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut entry = MaybeUninit::<libc::passwd>::uninit();
+    let mut result = ptr::null_mut();
+    // SAFETY: `getpwnam_r` fills `entry` with pointers into `buf`, which outlives every use below,
+    // and sets `result` to null unless the user was found.
+    unsafe {
+        let status = libc::getpwnam_r(
+            name.as_ptr(),
+            entry.as_mut_ptr(),
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        );
+        if status != 0 || result.is_null() || (*result).pw_dir.is_null() {
+            return None;
+        }
+        Some(
+            CStr::from_ptr((*result).pw_dir)
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+}
+
+#[cfg(not(unix))]
+fn home_dir_of(_user: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::expand::{expand_path, home_dir_of};
+
+    /// `tail` under `home`, or `path` as is when there's no home to expand it to, as `HOME` is
+    /// usually unset on Windows.
+    fn under_home(home: Option<String>, tail: &str, path: &str) -> String {
+        home.map_or_else(
+            || path.to_string(),
+            |home| format!("{}{tail}", home.trim_end_matches('/')),
+        )
+    }
+
+    #[test]
+    fn expands_home_directory() {
+        let home = std::env::var("HOME").ok();
+        assert_eq!(
+            expand_path("~/a/b.rs"),
+            under_home(home, "/a/b.rs", "~/a/b.rs")
+        );
+        assert_eq!(expand_path("a/~/b.rs"), "a/~/b.rs");
+    }
+
+    #[test]
+    fn expands_home_directory_of_user() {
+        // /root on Linux, /var/root on macOS and unknown on Windows.
+        assert_eq!(
+            expand_path("~root/a.rs"),
+            under_home(home_dir_of("root"), "/a.rs", "~root/a.rs")
+        );
+        #[cfg(target_os = "linux")]
+        assert_eq!(expand_path("~root/a.rs"), "/root/a.rs");
+        assert_eq!(
+            expand_path("~no-such-user-rfp/a.rs"),
+            "~no-such-user-rfp/a.rs"
+        );
+    }
+
+    #[test]
+    fn expands_environment_variables() {
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(expand_path("$PATH/a.rs"), format!("{path}/a.rs"));
+        assert_eq!(expand_path("${PATH}x/a.rs"), format!("{path}x/a.rs"));
+        assert_eq!(
+            expand_path("$RFP_SURELY_UNSET_VAR/a.rs"),
+            "$RFP_SURELY_UNSET_VAR/a.rs"
+        );
+    }
+}
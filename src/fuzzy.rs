@@ -0,0 +1,158 @@
+//! fzf-style fuzzy matching used by the TUI filter.
+//!
+//! A query matches a text when all of its characters appear in the text in order. Among the
+//! candidates, the shortest window containing the query is scored, rewarding matches that are
+//! consecutive or that start a path segment/word, and penalizing gaps in between.
+
+const SCORE_MATCH: i64 = 16;
+const SCORE_GAP_START: i64 = -3;
+const SCORE_GAP_EXTENSION: i64 = -1;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL_CASE: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Char (not byte) indices of the matched characters in the text.
+    pub positions: Vec<usize>,
+}
+
+/// Matches `query` against `text`. Matching is case-insensitive unless the query contains an
+/// uppercase character (smart case). An empty query matches everything with a zero score.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<FuzzyMatch> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let normalize = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_ascii_lowercase()
+        }
+    };
+    let query: Vec<char> = query.chars().map(normalize).collect();
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = original.iter().copied().map(normalize).collect();
+    if query.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: vec![],
+        });
+    }
+
+    // Forward pass: find where the earliest complete match ends.
+    let mut qi = 0;
+    let mut end = None;
+    for (i, &c) in text.iter().enumerate() {
+        if c == query[qi] {
+            qi += 1;
+            if qi == query.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // Backward pass: shrink the window from the left so that it's as tight as possible.
+    let mut qi = query.len();
+    let mut start = end;
+    for i in (0..=end).rev() {
+        if text[i] == query[qi - 1] {
+            qi -= 1;
+            if qi == 0 {
+                start = i;
+                break;
+            }
+        }
+    }
+
+    let mut positions = Vec::with_capacity(query.len());
+    let mut qi = 0;
+    for (i, &c) in text.iter().enumerate().take(end + 1).skip(start) {
+        if qi < query.len() && c == query[qi] {
+            positions.push(i);
+            qi += 1;
+        }
+    }
+
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    // Consecutive characters inherit the bonus of the first character of their chunk, so that
+    // e.g. a whole segment matching after a `/` beats the same letters scattered across segments.
+    let mut chunk_bonus = 0;
+    for (n, &i) in positions.iter().enumerate() {
+        let mut bonus = bonus_at(&original, i);
+        match previous {
+            Some(p) if i == p + 1 => {
+                bonus = bonus.max(chunk_bonus).max(BONUS_CONSECUTIVE);
+            }
+            Some(p) => {
+                score += SCORE_GAP_START + SCORE_GAP_EXTENSION * (i - p - 2) as i64;
+                chunk_bonus = bonus;
+            }
+            None => chunk_bonus = bonus,
+        }
+        if n == 0 {
+            bonus *= BONUS_FIRST_CHAR_MULTIPLIER;
+        }
+        score += SCORE_MATCH + bonus;
+        previous = Some(i);
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+fn bonus_at(text: &[char], i: usize) -> i64 {
+    if i == 0 {
+        return BONUS_BOUNDARY;
+    }
+    let (prev, cur) = (text[i - 1], text[i]);
+    if matches!(prev, '/' | '\\' | '_' | '-' | '.' | ' ' | ':') {
+        BONUS_BOUNDARY
+    } else if prev.is_lowercase() && cur.is_uppercase() {
+        BONUS_CAMEL_CASE
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fuzzy::fuzzy_match;
+
+    #[test]
+    fn matches_subsequence() {
+        assert_eq!(
+            fuzzy_match("mrs", "src/main.rs").unwrap().positions,
+            vec![4, 9, 10]
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_order_characters() {
+        assert!(fuzzy_match("sm", "main").is_none());
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(fuzzy_match("main", "src/Main.rs").is_some());
+        assert!(fuzzy_match("Main", "src/main.rs").is_none());
+    }
+
+    #[test]
+    fn prefers_tight_window() {
+        assert_eq!(
+            fuzzy_match("ab", "a_xxxx_ab").unwrap().positions,
+            vec![7, 8]
+        );
+    }
+
+    #[test]
+    fn prefers_consecutive_and_boundary_matches() {
+        let boundary = fuzzy_match("tui", "src/tui.rs").unwrap().score;
+        let scattered = fuzzy_match("tui", "src/t_u_i.rs").unwrap().score;
+        let inner = fuzzy_match("tui", "src/stui.rs").unwrap().score;
+        assert!(boundary > scattered);
+        assert!(boundary > inner);
+    }
+}
@@ -0,0 +1,334 @@
+//! Understands the output of `git status`, in the long format as well as `--short` and
+//! `--porcelain` v1 and v2, so that every entry yields the path it's about, with its status code.
+
+use crate::matcher::{LineMatch, MatchResult, StreamMatcher, Window, looks_like_path};
+use crate::os_str;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::ops::Range;
+
+lazy_static! {
+    // M  a.rs, ?? dir/ or R  old.rs -> new.rs, as printed by `git status --short` and
+    // `--porcelain`.
+    static ref SHORT_STATUS_REGEX: Regex =
+        Regex::new(r"^(?P<xy>[ MTADRCU?!]{2}) [^ ]").unwrap();
+    // 	modified:   a.rs, under one of the headings of the long format.
+    static ref LONG_STATUS_REGEX: Regex = Regex::new(
+        r"^\t(?:(?P<label>new file|modified|deleted|renamed|copied|typechange|both modified|both added|both deleted|added by us|added by them|deleted by us|deleted by them):\s+)?",
+    )
+    .unwrap();
+    // What the long format says about a submodule, after its path.
+    static ref SUBMODULE_SUFFIX_REGEX: Regex =
+        Regex::new(r" \((?:new commits|modified content|untracked content)(?:, [a-z ]+)*\)$")
+            .unwrap();
+}
+
+/// Matches `git status` entries. The long format only tells untracked files by the heading they're
+/// listed under, which is kept until the next one.
+#[derive(Default)]
+pub struct GitStatusMatcher {
+    /// The status of the entries under the current heading that don't have a label.
+    unlabeled_status: Option<&'static str>,
+}
+
+impl StreamMatcher for GitStatusMatcher {
+    fn match_window(&mut self, window: &Window) -> Vec<LineMatch> {
+        let line = window.current();
+        if !line.is_empty() && !line.starts_with([' ', '\t']) {
+            self.unlabeled_status = match line {
+                "Untracked files:" => Some("??"),
+                "Ignored files:" => Some("!!"),
+                _ => None,
+            };
+        }
+        let long_or_v2 = self.match_long(line).or_else(|| match_porcelain_v2(line));
+        let is_short = long_or_v2.is_none();
+        let Some((status, start, separator)) = long_or_v2.or_else(|| match_short(line)) else {
+            return vec![];
+        };
+        let Some((mut span, mut path)) = entry_path(line, start, separator) else {
+            return vec![];
+        };
+        // A short entry's path runs to the end of the line, so text that merely looks like one may
+        // go on after it, as in ` D src/a.rs was removed`. Deleted paths can't be told from that
+        // by whether they exist, so one with spaces is only taken whole if it ends like a path.
+        if is_short
+            && status.contains('D')
+            && !line[start..].starts_with('"')
+            && let Some((first, _)) = path.split_once(' ')
+            && !path.rsplit(' ').next().is_some_and(looks_like_path)
+        {
+            path.truncate(first.len());
+            span = start..start + path.len();
+        }
+        vec![LineMatch {
+            span,
+            result: MatchResult {
+                path,
+                line_number: None,
+                column: None,
+                end_line: None,
+                git_status: Some(status),
+                resolved_path: None,
+            },
+        }]
+    }
+}
+
+/// Where an entry's path starts in the line, along with its status and how the path it was renamed
+/// from is separated from it, if it may have been.
+type Entry = (String, usize, Option<Separator>);
+
+#[derive(Clone, Copy)]
+enum Separator {
+    /// `old -> new`
+    Arrow,
+    /// `new<TAB>old`, in porcelain v2.
+    Tab,
+}
+
+impl GitStatusMatcher {
+    fn match_long(&self, line: &str) -> Option<Entry> {
+        let captures = LONG_STATUS_REGEX.captures(line)?;
+        let status = match captures.name("label").map(|m| m.as_str()) {
+            Some(label) => match label {
+                "new file" => "A",
+                "modified" => "M",
+                "deleted" => "D",
+                "renamed" => "R",
+                "copied" => "C",
+                "typechange" => "T",
+                "both modified" => "UU",
+                "both added" => "AA",
+                "both deleted" => "DD",
+                "added by us" => "AU",
+                "added by them" => "UA",
+                "deleted by us" => "DU",
+                _ => "UD",
+            },
+            None => self.unlabeled_status?,
+        };
+        let start = captures.get(0)?.end();
+        if line[start..].starts_with(char::is_whitespace) {
+            return None;
+        }
+        Some((status.to_string(), start, Some(Separator::Arrow)))
+    }
+}
+
+/// `1 .M N... 100644 100644 100644 <hash> <hash> a.rs`, `2 R. N... <modes> <hashes> R100 new.rs
+/// <TAB>old.rs`, `u UU N... <modes> <hashes> a.rs`, `? a.rs` or `! a.rs`.
+fn match_porcelain_v2(line: &str) -> Option<Entry> {
+    let (kind, rest) = line.split_once(' ')?;
+    let (fields, separator) = match kind {
+        "?" => return Some((String::from("??"), 2, None)),
+        "!" => return Some((String::from("!!"), 2, None)),
+        "1" => (6, None),
+        "2" => (7, Some(Separator::Tab)),
+        "u" => (8, None),
+        _ => return None,
+    };
+    let mut words = rest.splitn(fields + 2, ' ');
+    let xy = words.next()?;
+    let submodule = words.next()?;
+    let is_entry = xy.len() == 2
+        && xy.chars().all(|c| ".MTADRCU".contains(c))
+        && (submodule.starts_with('N') || submodule.starts_with('S'));
+    let path = words.nth(fields - 1)?;
+    if !is_entry || path.is_empty() {
+        return None;
+    }
+    Some((status_code(xy), line.len() - path.len(), separator))
+}
+
+fn match_short(line: &str) -> Option<Entry> {
+    let xy = SHORT_STATUS_REGEX.captures(line)?.name("xy")?.as_str();
+    // Both being blank would make any indented text an entry.
+    if xy == "  " {
+        return None;
+    }
+    Some((status_code(xy), 3, Some(Separator::Arrow)))
+}
+
+/// The staged and unstaged status letters without the blanks, e.g. `M` for ` M` or `.M`.
+fn status_code(xy: &str) -> String {
+    xy.replace('.', " ").trim().to_string()
+}
+
+/// The path of the entry starting at `start` in `line`, along with the byte range of the entry.
+/// A renamed entry's range covers the path it was renamed from too, so that only the new path is
+/// picked up.
+fn entry_path(
+    line: &str,
+    start: usize,
+    separator: Option<Separator>,
+) -> Option<(Range<usize>, String)> {
+    let line = SUBMODULE_SUFFIX_REGEX
+        .find(line)
+        .map_or(line, |suffix| &line[..suffix.start()]);
+    let rest = &line[start..];
+    let (first, first_len) = read_path(rest, separator)?;
+    let after = &rest[first_len..];
+    let path = match separator {
+        Some(Separator::Arrow) if after.starts_with(" -> ") => read_path(&after[4..], None)?.0,
+        _ => first,
+    };
+    Some((start..line.len(), path))
+}
+
+/// Reads the path at the start of `s`, up to the separator or the end. Paths with special
+/// characters are quoted and escaped as in C. Returns the path and how many bytes it took up.
+fn read_path(s: &str, separator: Option<Separator>) -> Option<(String, usize)> {
+    if let Some(quoted) = s.strip_prefix('"') {
+        let mut bytes = vec![];
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((os_str::decode(&bytes).into_owned(), i + 2)),
+                '\\' => {
+                    let (_, escaped) = chars.next()?;
+                    match escaped {
+                        'a' => bytes.push(0x07),
+                        'b' => bytes.push(0x08),
+                        't' => bytes.push(b'\t'),
+                        'n' => bytes.push(b'\n'),
+                        'v' => bytes.push(0x0b),
+                        'f' => bytes.push(0x0c),
+                        'r' => bytes.push(b'\r'),
+                        '0'..='3' => {
+                            let digits = [escaped, chars.next()?.1, chars.next()?.1];
+                            let octal: String = digits.iter().collect();
+                            bytes.push(u8::from_str_radix(&octal, 8).ok()?);
+                        }
+                        c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    }
+                }
+                c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        return None;
+    }
+    let len = match separator {
+        Some(Separator::Arrow) => s.find(" -> "),
+        Some(Separator::Tab) => s.find('\t'),
+        None => None,
+    }
+    .unwrap_or(s.len());
+    (len > 0).then(|| (s[..len].to_string(), len))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::git::GitStatusMatcher;
+    use crate::matcher::{StreamMatcher, Window};
+
+    /// The path and status of every entry in `lines`, in order.
+    fn entries(lines: &[&str]) -> Vec<(String, String)> {
+        let mut matcher = GitStatusMatcher::default();
+        (0..lines.len())
+            .flat_map(|i| matcher.match_window(&Window::new(lines, i)))
+            .map(|m| (m.result.path, m.result.git_status.unwrap()))
+            .collect()
+    }
+
+    fn expected(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+        entries
+            .iter()
+            .map(|&(path, status)| (String::from(path), String::from(status)))
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_long_format() {
+        let lines = [
+            "On branch main",
+            "Changes to be committed:",
+            "  (use \"git restore --staged <file>...\" to unstage)",
+            "\tnew file:   src/new.rs",
+            "\trenamed:    src/old name.rs -> src/new name.rs",
+            "",
+            "Changes not staged for commit:",
+            "\tmodified:   \"caf\\303\\251.rs\"",
+            "\tdeleted:    gone.rs",
+            "\tmodified:   vendor/lib (new commits, modified content)",
+            "",
+            "Untracked files:",
+            "  (use \"git add <file>...\" to include in what will be committed)",
+            "\ttarget/",
+            "\tnotes.txt",
+            "\té.txt",
+            "",
+            "no changes added to commit (use \"git add\" and/or \"git commit -a\")",
+            "\tnot an entry.txt",
+        ];
+        assert_eq!(
+            entries(&lines),
+            expected(&[
+                ("src/new.rs", "A"),
+                ("src/new name.rs", "R"),
+                ("café.rs", "M"),
+                ("gone.rs", "D"),
+                ("vendor/lib", "M"),
+                ("target/", "??"),
+                ("notes.txt", "??"),
+                ("é.txt", "??"),
+            ])
+        );
+    }
+
+    #[test]
+    fn matches_the_short_format() {
+        let lines = [
+            " M src/a.rs",
+            "A  src/b.rs",
+            "R  old.rs -> \"new\\tname.rs\"",
+            "D  gone.rs",
+            "?? target/",
+            " D src/c.rs was removed",
+            "D  docs/old notes.txt",
+            "   indented text",
+        ];
+        assert_eq!(
+            entries(&lines),
+            expected(&[
+                ("src/a.rs", "M"),
+                ("src/b.rs", "A"),
+                ("new\tname.rs", "R"),
+                ("gone.rs", "D"),
+                ("target/", "??"),
+                ("src/c.rs", "D"),
+                ("docs/old notes.txt", "D"),
+            ])
+        );
+    }
+
+    #[test]
+    fn matches_porcelain_v2() {
+        let hash = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+        let lines = [
+            String::from("# branch.head main"),
+            format!("1 .M N... 100644 100644 100644 {hash} {hash} src/my file.rs"),
+            format!("2 R. N... 100644 100644 100644 {hash} {hash} R100 new.rs\told.rs"),
+            format!("u UU N... 100644 100644 100644 100644 {hash} {hash} {hash} both.rs"),
+            String::from("? untracked.txt"),
+            String::from("1 in a list"),
+        ];
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        assert_eq!(
+            entries(&lines),
+            expected(&[
+                ("src/my file.rs", "M"),
+                ("new.rs", "R"),
+                ("both.rs", "UU"),
+                ("untracked.txt", "??"),
+            ])
+        );
+    }
+
+    #[test]
+    fn covers_both_paths_of_a_rename() {
+        let line = "R  old.rs -> new.rs";
+        let found = GitStatusMatcher::default().match_window(&Window::new(&[line], 0));
+        assert_eq!(found[0].span, 3..line.len());
+    }
+}
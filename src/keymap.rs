@@ -0,0 +1,305 @@
+//! Key bindings for the TUI's list view. Prompts (`/`, `:`) take text input and aren't remappable,
+//! and neither is ctrl-c.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    Quit,
+    ToggleHelp,
+    Up,
+    Down,
+    HalfPageUp,
+    HalfPageDown,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    ToggleSelection,
+    Submit,
+    SelectAll,
+    PreviousSelected,
+    NextSelected,
+    Filter,
+    NextMatch,
+    PreviousMatch,
+    ToggleSources,
+    TogglePreview,
+    ScrollPreviewDown,
+    ScrollPreviewUp,
+    Command,
+}
+
+impl Action {
+    const ALL: [Action; 23] = [
+        Action::Quit,
+        Action::ToggleHelp,
+        Action::Up,
+        Action::Down,
+        Action::HalfPageUp,
+        Action::HalfPageDown,
+        Action::PageUp,
+        Action::PageDown,
+        Action::Top,
+        Action::Bottom,
+        Action::ToggleSelection,
+        Action::Submit,
+        Action::SelectAll,
+        Action::PreviousSelected,
+        Action::NextSelected,
+        Action::Filter,
+        Action::NextMatch,
+        Action::PreviousMatch,
+        Action::ToggleSources,
+        Action::TogglePreview,
+        Action::ScrollPreviewDown,
+        Action::ScrollPreviewUp,
+        Action::Command,
+    ];
+
+    /// The name used for the action in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::ToggleHelp => "help",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::HalfPageUp => "half_page_up",
+            Action::HalfPageDown => "half_page_down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::ToggleSelection => "toggle_selection",
+            Action::Submit => "submit",
+            Action::SelectAll => "select_all",
+            Action::PreviousSelected => "previous_selected",
+            Action::NextSelected => "next_selected",
+            Action::Filter => "filter",
+            Action::NextMatch => "next_match",
+            Action::PreviousMatch => "previous_match",
+            Action::ToggleSources => "input_lines",
+            Action::TogglePreview => "preview",
+            Action::ScrollPreviewDown => "preview_down",
+            Action::ScrollPreviewUp => "preview_up",
+            Action::Command => "command",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["q", "esc"],
+            Action::ToggleHelp => &["?"],
+            Action::Up => &["k", "up"],
+            Action::Down => &["j", "down"],
+            Action::HalfPageUp => &["u"],
+            Action::HalfPageDown => &["d"],
+            Action::PageUp => &["b", "pageup"],
+            Action::PageDown => &["f", "pagedown"],
+            Action::Top => &["g", "home"],
+            Action::Bottom => &["G", "end"],
+            Action::ToggleSelection => &["space"],
+            Action::Submit => &["enter"],
+            Action::SelectAll => &["a"],
+            Action::PreviousSelected => &["h", "left"],
+            Action::NextSelected => &["l", "right"],
+            Action::Filter => &["/"],
+            Action::NextMatch => &["n"],
+            Action::PreviousMatch => &["N"],
+            Action::ToggleSources => &["s"],
+            Action::TogglePreview => &["p"],
+            Action::ScrollPreviewDown => &["J"],
+            Action::ScrollPreviewUp => &["K"],
+            Action::Command => &[":"],
+        }
+    }
+}
+
+/// A key press, normalized so that shifted characters compare equal regardless of whether the
+/// terminal reports the shift modifier.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        KeyBinding { code, modifiers }
+    }
+
+    /// Parses keys such as `j`, `G`, `space`, `pagedown` or `ctrl-n`.
+    pub fn parse(key: &str) -> Result<Self, String> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = key;
+        loop {
+            if let Some(r) = rest.strip_prefix("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = r;
+            } else if let Some(r) = rest.strip_prefix("alt-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = r;
+            } else {
+                break;
+            }
+        }
+        let code = match rest {
+            "space" => KeyCode::Char(' '),
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            _ => {
+                let mut chars = rest.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(format!("Unknown key `{key}`")),
+                }
+            }
+        };
+        Ok(KeyBinding::new(code, modifiers))
+    }
+
+    fn label(&self) -> String {
+        let key = match self.code {
+            KeyCode::Char(' ') => "space".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Enter => "enter".to_string(),
+            KeyCode::Esc => "esc".to_string(),
+            KeyCode::Tab => "tab".to_string(),
+            KeyCode::Backspace => "backspace".to_string(),
+            KeyCode::Up => "↑".to_string(),
+            KeyCode::Down => "↓".to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            code => format!("{code:?}"),
+        };
+        let mut label = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            label.push_str("ctrl-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            label.push_str("alt-");
+        }
+        label + &key
+    }
+}
+
+impl From<KeyEvent> for KeyBinding {
+    fn from(key: KeyEvent) -> Self {
+        KeyBinding::new(key.code, key.modifiers)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: HashMap<Action, Vec<KeyBinding>>,
+    actions: HashMap<KeyBinding, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+            actions: HashMap::new(),
+        };
+        for action in Action::ALL {
+            let keys = action
+                .default_keys()
+                .iter()
+                .map(|k| KeyBinding::parse(k).expect("Default key bindings should be valid"))
+                .collect();
+            keymap.bind(action, keys);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Replaces the keys bound to `action`. Keys that were bound to another action are taken
+    /// away from it.
+    pub fn bind(&mut self, action: Action, keys: Vec<KeyBinding>) {
+        if let Some(previous) = self.bindings.remove(&action) {
+            for key in previous {
+                self.actions.remove(&key);
+            }
+        }
+        for key in &keys {
+            if let Some(other) = self.actions.insert(*key, action)
+                && let Some(other_keys) = self.bindings.get_mut(&other)
+            {
+                other_keys.retain(|k| k != key);
+            }
+        }
+        self.bindings.insert(action, keys);
+    }
+
+    pub fn action(&self, key: KeyEvent) -> Option<Action> {
+        self.actions.get(&KeyBinding::from(key)).copied()
+    }
+
+    /// The keys bound to `action` for display, e.g. `k/↑`.
+    pub fn label(&self, action: Action) -> String {
+        self.bindings
+            .get(&action)
+            .map(|keys| keys.iter().map(KeyBinding::label).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keymap::{Action, KeyBinding, Keymap};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(
+            KeyBinding::parse("ctrl-n").unwrap(),
+            KeyBinding::from(KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL))
+        );
+        assert_eq!(
+            KeyBinding::parse("G").unwrap(),
+            KeyBinding::from(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT))
+        );
+        assert!(KeyBinding::parse("hyper-x").is_err());
+    }
+
+    #[test]
+    fn rebinding_steals_keys_from_other_actions() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Action::Down, vec![KeyBinding::parse("n").unwrap()]);
+        let n = KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE);
+        let j = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(keymap.action(n), Some(Action::Down));
+        assert_eq!(keymap.action(j), None);
+        assert_eq!(keymap.label(Action::NextMatch), "");
+    }
+
+    #[test]
+    fn labels_default_bindings() {
+        assert_eq!(Keymap::default().label(Action::Up), "k/↑");
+    }
+}
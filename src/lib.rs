@@ -4,6 +4,20 @@
 //!
 //! TODO - complete the doc
 
+pub mod ansi;
+pub mod command;
+pub mod config;
+pub mod editor;
+pub mod expand;
+pub mod fuzzy;
+pub mod git;
+pub mod keymap;
 pub mod matcher;
+pub mod os_str;
+pub mod output;
 pub mod pipe;
+pub mod preview;
+pub mod range;
+pub mod resolve;
+pub mod stack_trace;
 pub mod tui;
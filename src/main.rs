@@ -1,22 +1,133 @@
-use log::{debug, warn};
-use rfp::tui::TUILoopEvent;
-use rfp::{pipe, tui};
+use clap::{ArgAction, CommandFactory, Parser};
+use clap_complete::Shell;
+use log::{LevelFilter, debug, warn};
+use rfp::command;
+use rfp::config::{self, FileConfig};
+use rfp::editor::Editor;
+use rfp::matcher::MatchResult;
+use rfp::output::{self, OutputFormat};
+use rfp::pipe::{self, Candidate, Found, PipeOptions};
+use rfp::tui::{Picker, PickerOptions, TUILoopEvent};
 use std::env;
+use std::fs::File;
 use std::io::{self, IsTerminal, stdin};
-use std::process::Command;
+use std::path::PathBuf;
+
+/// rfp - lets you interactively select files from piped input and open them in your editor
+#[derive(Parser)]
+#[command(
+    name = "rfp",
+    version = concat!("v", env!("CARGO_PKG_VERSION")),
+    disable_version_flag = true,
+    override_usage = "<command> | rfp [OPTIONS]"
+)]
+struct Cli {
+    /// Editor command to open the selection with, overriding $RFP_EDITOR, $VISUAL and $EDITOR.
+    #[arg(short, long, value_name = "EDITOR")]
+    editor: Option<String>,
+
+    /// Run COMMAND on the selection instead of opening the editor. $F expands to all paths, {} and
+    /// {line} to each path and its line number.
+    #[arg(short, long, value_name = "COMMAND")]
+    command: Option<String>,
+
+    /// Open the selection in Vim or Neovim as a quickfix list, to step through with :cnext.
+    #[arg(short, long)]
+    quickfix: bool,
+
+    /// Print the selection to stdout instead of opening the editor.
+    #[arg(short, long)]
+    print: bool,
+
+    /// Print the selection NUL-separated instead of newline-separated. Implies --print.
+    #[arg(long, conflicts_with = "json")]
+    print0: bool,
+
+    /// Print the selection as JSON lines. Implies --print.
+    #[arg(long)]
+    json: bool,
+
+    /// Print paths as path:line, or path:line:column when the column is known.
+    #[arg(long)]
+    with_position: bool,
+
+    /// Read NUL-separated paths, as printed by `find -print0` or `git ls-files -z`, instead of
+    /// searching lines for paths. Detected when the input starts that way. A printed selection is
+    /// NUL-separated too.
+    #[arg(short = '0', long)]
+    null: bool,
+
+    /// List every line a path is found on, e.g. each grep hit, instead of the path once.
+    #[arg(long)]
+    per_line: bool,
+
+    /// Skip the selection and act on every path found.
+    #[arg(short, long)]
+    non_interactive: bool,
+
+    /// Start with every path selected.
+    #[arg(short = 'a', long)]
+    select_all: bool,
+
+    /// Go back to the selection after the editor or command exits.
+    #[arg(short, long, conflicts_with = "non_interactive")]
+    keep_open: bool,
+
+    /// Read the configuration from PATH instead of $XDG_CONFIG_HOME/rfp/config.toml.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Log verbosity, overriding $RUST_LOG.
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<LevelFilter>,
+
+    /// Print the completion script for SHELL and exit.
+    #[arg(long, value_name = "SHELL")]
+    completions: Option<Shell>,
+
+    /// Print version.
+    #[arg(short = 'v', long, action = ArgAction::Version)]
+    version: (),
+}
 
 struct Config {
-    editor: String,
+    /// Shell command to run on the selection instead of opening the editor.
+    command: Option<String>,
+    editor: Editor,
+    keep_open: bool,
+    non_interactive: bool,
+    picker_options: PickerOptions,
+    pipe_options: PipeOptions,
+    /// Print the selection to stdout in this format instead of opening the editor.
+    print_format: Option<OutputFormat>,
+    /// Suffix printed paths with their position.
+    print_with_position: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            editor: "vim".to_string(),
+            command: None,
+            editor: Editor {
+                program: "vim".to_string(),
+                args: vec![],
+                template: None,
+                quickfix: false,
+            },
+            keep_open: false,
+            non_interactive: false,
+            picker_options: PickerOptions::default(),
+            pipe_options: PipeOptions::default(),
+            print_format: None,
+            print_with_position: false,
         }
     }
 }
 
+/// Where the editor command is read from, in order of precedence. The config file's
+/// `editor.command` sits between $RFP_EDITOR and $VISUAL.
+const EDITOR_ENV_VARS: &[&str] = &["RFP_EDITOR", "VISUAL", "EDITOR"];
+
 type ExitCode = i32;
 
 const EXIT_ERROR: ExitCode = 1;
@@ -24,67 +135,203 @@ const EXIT_USAGE_ERROR: ExitCode = 2;
 const EXIT_INTERRUPTED: ExitCode = 130;
 
 fn main() -> io::Result<()> {
-    env_logger::init();
-
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        match args[1].as_str() {
-            "--help" | "-h" => {
-                println!(
-                    "rfp - lets you interactively select files from piped input and open them in your editor\n\nUsage: <command> | rfp"
-                );
-                return Ok(());
-            }
-            "--version" | "-v" | "version" => {
-                println!("rfp v{}", env!("CARGO_PKG_VERSION"));
-                return Ok(());
-            }
-            _ => {
-                eprintln!("Error: Unknown argument '{}'", args[1]);
-                eprintln!("Use --help for usage");
-                std::process::exit(EXIT_USAGE_ERROR);
-            }
-        }
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        let code = if e.use_stderr() { EXIT_USAGE_ERROR } else { 0 };
+        let _ = e.print();
+        std::process::exit(code);
+    });
+
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = cli.log_level {
+        logger.filter_level(level);
     }
+    logger.init();
 
-    let config = preflight_check().unwrap_or_else(|e| {
+    if let Some(shell) = cli.completions {
+        clap_complete::generate(shell, &mut Cli::command(), "rfp", &mut io::stdout());
+        return Ok(());
+    }
+
+    let mut config = preflight_check(cli).unwrap_or_else(|e| {
         eprintln!("Error: {e}");
         std::process::exit(EXIT_USAGE_ERROR);
     });
 
-    let candidates = pipe::run().unwrap_or_else(|e| {
-        eprintln!("Error: {e}");
-        std::process::exit(EXIT_ERROR);
-    });
-    let (paths, action) = tui::run(candidates).unwrap_or_else(|e| {
-        eprintln!("Error: {e}");
-        std::process::exit(EXIT_ERROR);
-    });
+    let candidates = pipe::run(std::mem::take(&mut config.pipe_options));
+    if config.non_interactive {
+        let mut selections: Vec<Candidate> = vec![];
+        for found in candidates {
+            match found {
+                Ok(Found::Candidate(candidate)) => selections.push(candidate),
+                Ok(Found::Positioned(i, position)) => {
+                    selections[i].result.take_position(&position);
+                }
+                Ok(Found::Again(..)) => {}
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    std::process::exit(EXIT_ERROR);
+                }
+            }
+        }
+        let action = if selections.is_empty() {
+            TUILoopEvent::EarlyReturn
+        } else {
+            TUILoopEvent::Submit
+        };
+        return act_on_selection(&config, selections, action);
+    }
 
-    if paths.is_empty() {
-        if action == TUILoopEvent::Submit {
-            println!("No paths selected.");
+    let mut picker = Picker::new(candidates, std::mem::take(&mut config.picker_options));
+    loop {
+        let (selections, action) = picker.run().unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            std::process::exit(EXIT_ERROR);
+        });
+        let is_acted_on = !selections.is_empty()
+            && matches!(action, TUILoopEvent::Submit | TUILoopEvent::Command(_));
+        act_on_selection(&config, selections, action)?;
+        if !config.keep_open || !is_acted_on {
+            break Ok(());
+        }
+    }
+}
+
+fn act_on_selection(
+    config: &Config,
+    selections: Vec<Candidate>,
+    action: TUILoopEvent,
+) -> io::Result<()> {
+    // When printing, stdout belongs to the selection so that it can be piped somewhere else.
+    let notify = |message: &str| {
+        if config.print_format.is_some() {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
+        }
+    };
+    if selections.is_empty() {
+        if matches!(action, TUILoopEvent::Submit | TUILoopEvent::Command(_)) {
+            notify("No paths selected.");
         } else if action == TUILoopEvent::EarlyReturn {
-            println!("No paths found.");
+            notify("No paths found.");
         } else if action == TUILoopEvent::Interrupted {
-            println!("Interrupted.");
+            notify("Interrupted.");
             std::process::exit(EXIT_INTERRUPTED);
         }
+    } else if let TUILoopEvent::Command(command) = action {
+        run_command(&command, &results(&selections))?;
+    } else if let Some(command) = &config.command {
+        run_command(command, &results(&selections))?;
+    } else if let Some(format) = config.print_format {
+        output::write_selection(
+            &mut io::stdout().lock(),
+            &results(&selections),
+            format,
+            config.print_with_position,
+        )?;
     } else {
-        Command::new(config.editor).args(paths).status()?;
+        let mut command = config.editor.command(&selections)?;
+        let command_line = command.command_line().to_string();
+        debug!("Launching {command_line}");
+        // The reader thread may still be draining the pipe, so hand the editor the terminal instead.
+        if let Ok(tty) = File::open("/dev/tty") {
+            command.stdin(tty);
+        }
+        if let Err(e) = command.status() {
+            eprintln!("Error: Could not launch the editor with `{command_line}`: {e}");
+            std::process::exit(EXIT_ERROR);
+        }
     }
     Ok(())
 }
 
-fn preflight_check() -> Result<Config, Box<dyn std::error::Error>> {
+fn results(selections: &[Candidate]) -> Vec<MatchResult> {
+    selections.iter().map(|c| c.result.clone()).collect()
+}
+
+fn run_command(command: &str, selections: &[MatchResult]) -> io::Result<()> {
+    for (command_line, status) in command::run(command, selections)? {
+        debug!("Ran `{command_line}`");
+        println!("`{command_line}` exited with {status}");
+    }
+    Ok(())
+}
+
+fn preflight_check(cli: Cli) -> Result<Config, Box<dyn std::error::Error>> {
     if stdin().is_terminal() {
         return Err("No input provided. Please pipe data to thie command.".into());
     }
+    let file_config = match cli
+        .config
+        .or_else(|| config::default_path().filter(|p| p.is_file()))
+    {
+        Some(path) => {
+            debug!("Reading config from {}", path.display());
+            config::load(&path)?
+        }
+        None => FileConfig::default(),
+    };
+
     let mut config = Config::default();
-    config.editor = env::var("EDITOR").unwrap_or_else(|_| {
-        warn!("EDITOR environment variable not set, using vim");
-        config.editor
-    });
-    debug!("Read $EDITOR: {}", config.editor);
+    let env_editor = |var: &&str| {
+        env::var(var)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .inspect(|v| debug!("Read ${var}: {v}"))
+    };
+    let (before_config, after_config) = EDITOR_ENV_VARS.split_at(1);
+    let editor = match cli
+        .editor
+        .or_else(|| before_config.iter().find_map(env_editor))
+    {
+        Some(editor) => Some(Editor::parse(&editor)?),
+        None => match file_config.editor {
+            Some(editor) => Some(editor),
+            None => after_config
+                .iter()
+                .find_map(env_editor)
+                .map(|editor| Editor::parse(&editor))
+                .transpose()?,
+        },
+    };
+    match editor {
+        Some(editor) => config.editor = editor,
+        None => warn!("None of RFP_EDITOR, VISUAL or EDITOR is set, using vim"),
+    }
+    config.editor.apply_templates(&file_config.editor_templates);
+    config.editor.quickfix = cli.quickfix || file_config.editor_quickfix;
+
+    config.print_format = if cli.json {
+        Some(OutputFormat::JsonLines)
+    } else if cli.print0 {
+        Some(OutputFormat::Null)
+    } else if cli.print {
+        Some(OutputFormat::Newline)
+    } else {
+        file_config.print_format
+    };
+    // Paths read NUL-separated may contain newlines, so they can't be printed one per line.
+    if cli.null && config.print_format == Some(OutputFormat::Newline) {
+        config.print_format = Some(OutputFormat::Null);
+    }
+    config.print_with_position = cli.with_position || file_config.with_position;
+    let per_line = cli.per_line || file_config.per_line;
+    config.pipe_options = PipeOptions {
+        extra_regexes: file_config.matchers,
+        ignore: file_config.ignore,
+        null_delimited: cli.null,
+        per_line,
+        roots: file_config.roots,
+        strip_prefixes: file_config.strip_prefixes,
+    };
+    config.picker_options = PickerOptions {
+        keymap: file_config.keymap,
+        per_line,
+        select_all: cli.select_all,
+        theme: file_config.theme,
+    };
+    config.command = cli.command;
+    config.keep_open = cli.keep_open;
+    config.non_interactive = cli.non_interactive;
     Ok(config)
 }
@@ -1,25 +1,161 @@
+use crate::expand::expand_path;
+use crate::os_str;
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 pub trait Matcher {
-    fn match_line(&self, line: &str) -> Option<MatchResult>;
+    /// Every match in `line`, from the most trusted to the least. They may overlap, as which of
+    /// them wins depends on whether their paths exist, which is up to the caller to check before
+    /// [`select_non_overlapping`].
+    fn match_all(&self, line: &str) -> Vec<LineMatch>;
+
+    /// The leftmost match in `line`, overlaps resolved without checking what exists.
+    fn match_line(&self, line: &str) -> Option<MatchResult> {
+        select_non_overlapping(self.match_all(line))
+            .into_iter()
+            .next()
+            .map(|m| m.result)
+    }
+}
+
+/// How many lines a [`StreamMatcher`] can see on either side of the current one.
+pub const WINDOW_LINES: usize = 16;
+
+/// A matcher fed the input one line at a time, in order, for formats that spread a location over
+/// several lines, such as diff hunks under their `+++` header. It may keep state from one line to
+/// the next, and sees the lines around the current one through a [`Window`].
+///
+/// Every [`Matcher`] is one that only looks at the current line.
+pub trait StreamMatcher {
+    /// How many lines after the current one it needs to see, up to [`WINDOW_LINES`]. Matches are
+    /// held back until those lines have been read, or the input ends.
+    fn lookahead(&self) -> usize {
+        0
+    }
+
+    /// Every match in the current line of `window`, from the most trusted to the least, as with
+    /// [`Matcher::match_all`].
+    fn match_window(&mut self, window: &Window) -> Vec<LineMatch>;
+}
+
+impl<M: Matcher> StreamMatcher for M {
+    fn match_window(&mut self, window: &Window) -> Vec<LineMatch> {
+        self.match_all(window.current())
+    }
+}
+
+/// The line being matched, along with the lines read around it.
+pub struct Window<'a> {
+    lines: &'a [&'a str],
+    current: usize,
+}
+
+impl<'a> Window<'a> {
+    /// `lines[current]` being the line to match.
+    pub fn new(lines: &'a [&'a str], current: usize) -> Self {
+        Self { lines, current }
+    }
+
+    pub fn current(&self) -> &'a str {
+        self.lines[self.current]
+    }
+
+    /// The line `n` lines before the current one, 1 being the previous one, if it's in the window.
+    pub fn before(&self, n: usize) -> Option<&'a str> {
+        self.current.checked_sub(n).map(|i| self.lines[i])
+    }
+
+    /// The line `n` lines after the current one, 1 being the next one, if it's been read and is
+    /// in the window.
+    pub fn after(&self, n: usize) -> Option<&'a str> {
+        self.lines.get(self.current + n).copied()
+    }
+}
+
+/// A match along with the byte range of its path in the line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineMatch {
+    pub span: Range<usize>,
+    pub result: MatchResult,
+}
+
+/// Keeps the matches that don't overlap an earlier one, and orders them from left to right.
+/// `matches` should therefore come from the most trusted to the least.
+pub fn select_non_overlapping(matches: impl IntoIterator<Item = LineMatch>) -> Vec<LineMatch> {
+    let mut selected: Vec<LineMatch> = vec![];
+    for m in matches {
+        if selected
+            .iter()
+            .all(|s| m.span.end <= s.span.start || s.span.end <= m.span.start)
+        {
+            selected.push(m);
+        }
+    }
+    selected.sort_by_key(|m| m.span.start);
+    selected
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct MatchResult {
     pub path: String,
     pub line_number: Option<u32>,
+    pub column: Option<u32>,
+    /// The last line of a range that starts at `line_number`, e.g. a diff hunk.
+    pub end_line: Option<u32>,
+    /// The status code `git status` gave the path, e.g. `M` or `??`.
+    pub git_status: Option<String>,
+    /// Where the file was found when `path` doesn't lead to it, e.g. under the repository root.
+    pub resolved_path: Option<String>,
+}
+
+impl MatchResult {
+    /// The path with `~` and environment variables expanded, or where it was resolved to, for
+    /// checking and opening it. `path` is kept as it appeared in the input for display.
+    pub fn expanded_path(&self) -> Cow<'_, str> {
+        match &self.resolved_path {
+            Some(resolved) => Cow::Borrowed(resolved),
+            None => expand_path(&self.path),
+        }
+    }
+
+    /// Takes the line, column and last line of `other`, a match of the same path.
+    pub fn take_position(&mut self, other: &MatchResult) {
+        self.line_number = other.line_number;
+        self.column = other.column;
+        self.end_line = other.end_line;
+    }
+
+    /// Whether `git status` lists the path as deleted, in the index or the worktree.
+    pub fn is_deleted(&self) -> bool {
+        self.git_status.as_deref().is_some_and(|s| s.contains('D'))
+    }
+
+    /// The expanded path as the OS knows it, including bytes that weren't valid UTF-8.
+    pub fn fs_path(&self) -> PathBuf {
+        PathBuf::from(os_str::to_os_string(&self.expanded_path()))
+    }
 }
 
 #[derive(Clone)]
 struct RegexConfig {
     regex: Regex,
-    line_number_idx: usize,
+    path_idx: usize,
+    line_number_idx: Option<usize>,
+    column_idx: Option<usize>,
 }
 
 pub struct RegexMatcher {
+    /// Formats that pin down exactly where the path starts and ends, such as compiler
+    /// diagnostics. They are tried before anything else and never widened over spaces.
+    precise_configs: Vec<RegexConfig>,
     regex_configs: Vec<RegexConfig>,
+    /// Whether the spans tried so far exist on disk, as logs tend to repeat them.
+    exists_cache: RefCell<HashMap<String, bool>>,
 }
 
 impl Default for RegexMatcher {
@@ -29,62 +165,331 @@ impl Default for RegexMatcher {
 }
 
 impl Matcher for RegexMatcher {
-    fn match_line(&self, line: &str) -> Option<MatchResult> {
-        // -> Option<MatchResult> {
-        for regex_config in &self.regex_configs {
-            let RegexConfig {
-                regex,
-                line_number_idx,
-            } = regex_config;
-
-            if let Some(captures) = regex.captures(line) {
-                let path = self.post_processing(captures.get(1)?.as_str());
-                let line_number = captures
-                    .get(*line_number_idx)
-                    .and_then(|m| m.as_str().parse().ok());
-
-                return Some(MatchResult {
-                    path: path.to_string(),
-                    line_number,
-                });
-            }
-        }
-        None
+    fn match_all(&self, line: &str) -> Vec<LineMatch> {
+        let mut matches = self.match_configs(&self.precise_configs, line, false);
+        matches.extend(self.match_quoted(line));
+        matches.extend(self.match_escaped(line));
+        // The generic regexes are fallbacks for one another, e.g. a single file for a path, so a
+        // later one's match within an earlier one's is the same path cut short.
+        matches.extend(select_non_overlapping(self.match_configs(
+            &self.regex_configs,
+            line,
+            true,
+        )));
+        matches
     }
 }
 
 lazy_static! {
-    // TODO(bz): files/paths with spaces
+    static ref DIAGNOSTIC_REGEX_CONFIGS: Vec<RegexConfig> = vec![
+        // rustc and cargo. --> src/lib.rs:10:5
+        RegexConfig {
+            regex: Regex::new(r"-->\s+(.+?):(\d+):(\d+)\s*$").unwrap(),
+            path_idx: 1,
+            line_number_idx: Some(2),
+            column_idx: Some(3),
+        },
+        // gcc and clang. src/a.c:10:5: error: ...
+        RegexConfig {
+            regex: Regex::new(
+                r"^\s*((?:[A-Za-z]:)?[^\s:(]+):(\d+):(\d+): (?:fatal error|error|warning|note|remark):",
+            )
+            .unwrap(),
+            path_idx: 1,
+            line_number_idx: Some(2),
+            column_idx: Some(3),
+        },
+        // TypeScript. src/a.ts(10,5): error TS2322: ...
+        RegexConfig {
+            regex: Regex::new(
+                r"^\s*((?:[A-Za-z]:)?[^\s:(]+)\((\d+),(\d+)\): (?:error|warning) TS\d+:",
+            )
+            .unwrap(),
+            path_idx: 1,
+            line_number_idx: Some(2),
+            column_idx: Some(3),
+        },
+        // MSBuild, optionally with an end position and a node prefix.
+        // 1>C:\src\File.cs(10,5,10,9): error CS1002: ...
+        RegexConfig {
+            regex: Regex::new(
+                r"^\s*(?:\d+>)?((?:[A-Za-z]:)?[^\s:(]+)\((\d+),(\d+)(?:,\d+,\d+)?\): (?:fatal error|error|warning|message) [A-Z]+\d+:",
+            )
+            .unwrap(),
+            path_idx: 1,
+            line_number_idx: Some(2),
+            column_idx: Some(3),
+        },
+    ];
+}
+
+// Bytes that aren't valid UTF-8 are decoded to U+10FF80..U+10FFFF (see `os_str`), and count as
+// path characters.
+lazy_static! {
     static ref REGEX_CONFIGS: Vec<RegexConfig> = vec![
-        // Homedir paths. ~/a/b/c.ext:123
+        // Homedir paths, optionally of another user. ~/a/b/c.ext, ~alice/a/b/c.ext
+        RegexConfig {
+            regex: Regex::new(
+                r"(~[a-zA-Z0-9._-]*/([a-zA-Z0-9._\x{10FF80}-\x{10FFFF}-]+/)*[a-zA-Z0-9._\x{10FF80}-\x{10FFFF}-]+(\.[a-zA-Z0-9]{1,42})?)",
+            )
+            .unwrap(),
+            path_idx: 1,
+            line_number_idx: None,
+            column_idx: None,
+        },
+        // Paths under an environment variable. $HOME/a/b/c.ext, ${XDG_CONFIG_HOME}/a/b
         RegexConfig {
             regex: Regex::new(
-                r"(~/([a-zA-Z0-9._-]+/)*[a-zA-Z0-9._-]+(\.[a-zA-Z0-9]{1,42})?)[:-]?(\d+)?",
+                r"((?:\$[A-Za-z_][A-Za-z0-9_]*|\$\{[A-Za-z_][A-Za-z0-9_]*\})(/[a-zA-Z0-9._\x{10FF80}-\x{10FFFF}-]+)+)",
             )
             .unwrap(),
-            line_number_idx: 4,
+            path_idx: 1,
+            line_number_idx: None,
+            column_idx: None,
         },
-        // Standard paths, w/ or w/o extension. a/b/c.ext:123
+        // Standard paths, w/ or w/o extension. a/b/c.ext
         RegexConfig {
             regex: Regex::new(
-                r"(/?([a-zA-Z0-9._-]+/)+[a-zA-Z0-9._-]+(\.[a-zA-Z0-9]{1,42})?)[:-]?(\d+)?",
+                r"(/?([a-zA-Z0-9._\x{10FF80}-\x{10FFFF}-]+/)+[a-zA-Z0-9._\x{10FF80}-\x{10FFFF}-]+(\.[a-zA-Z0-9]{1,42})?)",
             )
             .unwrap(),
-            line_number_idx: 4,
+            path_idx: 1,
+            line_number_idx: None,
+            column_idx: None,
         },
         // Single file with extension
         RegexConfig {
-            regex: Regex::new(r"(/?[a-zA-Z0-9._-]+\.[a-zA-Z0-9]{1,42})[:-]?(\d+)?").unwrap(),
-            line_number_idx: 2,
+            regex: Regex::new(r"(/?[a-zA-Z0-9._\x{10FF80}-\x{10FFFF}-]+\.[a-zA-Z0-9]{1,42})").unwrap(),
+            path_idx: 1,
+            line_number_idx: None,
+            column_idx: None,
         },
     ];
+    // Quoted with either of "", '' or ``. "a b/c.ext"
+    static ref QUOTED_PATH_REGEX: Regex = Regex::new(r#""([^"]+)"|'([^']+)'|`([^`]+)`"#).unwrap();
+    // Spaces escaped with backslashes. a\ b/c.ext
+    static ref ESCAPED_PATH_REGEX: Regex = Regex::new(
+        r"((?:~/|/)?(?:[a-zA-Z0-9._\x{10FF80}-\x{10FFFF}-]|\\ )+(?:/(?:[a-zA-Z0-9._\x{10FF80}-\x{10FFFF}-]|\\ )+)*)",
+    )
+    .unwrap();
+    // A whitespace-delimited span, possibly followed by a position. a b/c.ext:123:4:
+    static ref SPACED_PATH_REGEX: Regex =
+        Regex::new(r"^(.+?)(?:[:-](\d+)(?::(\d+))?)?:?$").unwrap();
+    // Where in the file, right after the path and maybe its closing quote. :10, :10:5, :10-25,
+    // -10 as in grep context lines, #L10-L25 as in GitHub links, (10,5) as in MSVC, or
+    // ", line 10, column 5".
+    static ref POSITION_REGEX: Regex = Regex::new(
+        r#"^["'`]?(?:[:-](?P<line>\d+)(?::(?P<column>\d+)|-(?P<end_line>\d+))?|#L(?P<anchor_line>\d+)(?:-L(?P<anchor_end_line>\d+))?|\((?P<paren_line>\d+)(?:,\s*(?P<paren_column>\d+))?\)|[,:]?\s+line\s+(?P<word_line>\d+)(?:,?\s+col(?:umn)?\s+(?P<word_column>\d+))?)"#,
+    )
+    .unwrap();
+    static ref EXTENSION_REGEX: Regex = Regex::new(r"\.[a-zA-Z0-9]{1,42}$").unwrap();
 }
 
+/// How many whitespace-separated tokens the on-disk fallback may extend a match by, in either
+/// direction. Every combination costs a filesystem lookup, so keep this small.
+const MAX_SPACED_TOKENS: usize = 4;
+
+/// How many lookups `RegexMatcher` remembers.
+const MAX_CACHED_PATHS: usize = 4096;
+
 impl RegexMatcher {
     pub fn new() -> Self {
+        Self::with_extra_regexes(vec![])
+    }
+
+    /// Tries `regexes` before the built-in ones. The path is taken from their `path` group, and the
+    /// position from the optional `line` and `column` groups, or else from right after the path.
+    pub fn with_extra_regexes(regexes: Vec<Regex>) -> Self {
+        let group_idx =
+            |regex: &Regex, name: &str| regex.capture_names().position(|n| n == Some(name));
+        let mut precise_configs: Vec<RegexConfig> = regexes
+            .into_iter()
+            .filter_map(|regex| {
+                Some(RegexConfig {
+                    path_idx: group_idx(&regex, "path")?,
+                    line_number_idx: group_idx(&regex, "line"),
+                    column_idx: group_idx(&regex, "column"),
+                    regex,
+                })
+            })
+            .collect();
+        precise_configs.extend(DIAGNOSTIC_REGEX_CONFIGS.iter().cloned());
         Self {
+            precise_configs,
             regex_configs: REGEX_CONFIGS.clone(),
+            exists_cache: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Returns every match of every config in `configs`, in order of the configs. With `widen`,
+    /// paths that don't exist are retried with the surrounding whitespace-separated tokens.
+    fn match_configs(&self, configs: &[RegexConfig], line: &str, widen: bool) -> Vec<LineMatch> {
+        let mut matches = vec![];
+        for regex_config in configs {
+            let RegexConfig {
+                regex,
+                path_idx,
+                line_number_idx,
+                column_idx,
+            } = regex_config;
+
+            for captures in regex.captures_iter(line) {
+                let Some(path_match) = captures.get(*path_idx) else {
+                    continue;
+                };
+                let path = self.post_processing(path_match.as_str());
+                let number = |idx: &Option<usize>| {
+                    idx.and_then(|i| captures.get(i))
+                        .and_then(|m| m.as_str().parse().ok())
+                };
+
+                // The regexes stop at the first space, so a path like `My Docs/a.txt` comes back
+                // truncated. If what we got doesn't exist, see whether a wider span does.
+                if widen
+                    && line.contains(' ')
+                    && !self.exists(path)
+                    && let Some(line_match) =
+                        self.match_spaced(line, path_match.start(), path_match.end())
+                {
+                    matches.push(line_match);
+                    continue;
+                }
+
+                let result = match line_number_idx {
+                    Some(_) => MatchResult {
+                        path: path.to_string(),
+                        line_number: number(line_number_idx),
+                        column: number(column_idx),
+                        end_line: None,
+                        git_status: None,
+                        resolved_path: None,
+                    },
+                    None => with_position_after(path.to_string(), line, path_match.end()),
+                };
+                matches.push(LineMatch {
+                    span: path_match.end() - path.len()..path_match.end(),
+                    result,
+                });
+            }
+        }
+        matches
+    }
+
+    fn match_quoted(&self, line: &str) -> Vec<LineMatch> {
+        // Quoted paths without spaces are already handled by the regular regexes.
+        QUOTED_PATH_REGEX
+            .captures_iter(line)
+            .filter_map(|captures| {
+                let path = (1..=3).find_map(|i| captures.get(i))?;
+                if !path.as_str().contains(' ') || !looks_like_path(path.as_str()) {
+                    return None;
+                }
+                Some(LineMatch {
+                    span: path.range(),
+                    result: with_position_after(path.as_str().to_string(), line, path.end()),
+                })
+            })
+            .collect()
+    }
+
+    fn match_escaped(&self, line: &str) -> Vec<LineMatch> {
+        ESCAPED_PATH_REGEX
+            .captures_iter(line)
+            .filter_map(|captures| {
+                let path = captures.get(1)?;
+                if !path.as_str().contains("\\ ") || !looks_like_path(path.as_str()) {
+                    return None;
+                }
+                Some(LineMatch {
+                    span: path.range(),
+                    result: with_position_after(
+                        path.as_str().replace("\\ ", " "),
+                        line,
+                        path.end(),
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    /// Greedily widens the regex match at `start..end` over neighbouring whitespace-separated
+    /// tokens and returns the longest span that exists on disk. Tokens are only joined into a
+    /// directory that exists, which rules out most spans of prose with a single lookup.
+    fn match_spaced(&self, line: &str, start: usize, end: usize) -> Option<LineMatch> {
+        let tokens: Vec<(usize, usize)> = line
+            .split(' ')
+            .scan(0, |offset, token| {
+                let token_start = *offset;
+                *offset += token.len() + 1;
+                Some((token_start, token_start + token.len()))
+            })
+            .filter(|(s, e)| s < e)
+            .collect();
+        let first = tokens.iter().position(|&(_, e)| e > start)?;
+        let last = tokens.iter().rposition(|&(s, _)| s < end)?;
+
+        let mut spans: Vec<(usize, usize)> = (first.saturating_sub(MAX_SPACED_TOKENS)..=first)
+            .flat_map(|i| {
+                (last..=(last + MAX_SPACED_TOKENS).min(tokens.len() - 1)).map(move |j| (i, j))
+            })
+            .filter(|(i, j)| i < j)
+            .collect();
+        spans.sort_by_key(|&(i, j)| (std::cmp::Reverse(j - i), i));
+
+        spans.into_iter().find_map(|(i, j)| {
+            let offset = tokens[i].0;
+            let span = &line[offset..tokens[j].1];
+            if !self.spaced_dir_exists(span) {
+                return None;
+            }
+            let captures = SPACED_PATH_REGEX.captures(span)?;
+            let path = captures.get(1)?;
+            if !looks_like_path(path.as_str()) || !self.exists(path.as_str()) {
+                return None;
+            }
+            Some(LineMatch {
+                span: offset + path.start()..offset + path.end(),
+                result: MatchResult {
+                    path: path.as_str().to_string(),
+                    line_number: captures.get(2).and_then(|m| m.as_str().parse().ok()),
+                    column: captures.get(3).and_then(|m| m.as_str().parse().ok()),
+                    end_line: None,
+                    git_status: None,
+                    resolved_path: None,
+                },
+            })
+        })
+    }
+
+    /// Whether the directory the first space in `path` is in exists. That's the directory with the
+    /// space in its name, e.g. `My Docs` in `My Docs/a.txt`, or else the one holding the file
+    /// with the space in its name, e.g. `docs/` in `docs/a b.txt`.
+    fn spaced_dir_exists(&self, path: &str) -> bool {
+        let Some(space) = path.find(' ') else {
+            return true;
+        };
+        let dir = match path[space..].find('/') {
+            Some(slash) => &path[..space + slash],
+            None => match path[..space].rfind('/') {
+                Some(slash) => &path[..=slash],
+                None => return true,
+            },
+        };
+        self.exists(dir)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        let mut cache = self.exists_cache.borrow_mut();
+        if let Some(&exists) = cache.get(path) {
+            return exists;
+        }
+        // Keep long inputs with few repeated paths from growing it without bound.
+        if cache.len() >= MAX_CACHED_PATHS {
+            cache.clear();
         }
+        let exists = exists(path);
+        cache.insert(path.to_string(), exists);
+        exists
     }
 
     fn post_processing<'a>(&self, line: &'a str) -> &'a str {
@@ -96,8 +501,40 @@ impl RegexMatcher {
     }
 }
 
+fn exists(path: &str) -> bool {
+    Path::new(&os_str::to_os_string(&expand_path(path))).exists()
+}
+
+/// `path` at the position given right after it, which ends at `end` in `line`, if any.
+fn with_position_after(path: String, line: &str, end: usize) -> MatchResult {
+    let captures = POSITION_REGEX.captures(&line[end..]);
+    // Only one of the shapes matches, so only one name of each is set.
+    let number = |names: &[&str]| {
+        let captures = captures.as_ref()?;
+        names
+            .iter()
+            .find_map(|&name| captures.name(name))
+            .and_then(|m| m.as_str().parse().ok())
+    };
+    let line_number = number(&["line", "anchor_line", "paren_line", "word_line"]);
+    MatchResult {
+        path,
+        line_number,
+        column: number(&["column", "paren_column", "word_column"]),
+        end_line: number(&["end_line", "anchor_end_line"])
+            .filter(|&end_line| line_number.is_some_and(|line| line < end_line)),
+        git_status: None,
+        resolved_path: None,
+    }
+}
+
+/// Whether `path`, which may have spaces, has a directory or an extension to tell it from prose.
+pub fn looks_like_path(path: &str) -> bool {
+    path.trim() == path && (path.contains('/') || EXTENSION_REGEX.is_match(path))
+}
+
 lazy_static! {
-    static ref SINGLE_FILE_REGEX: Regex = Regex::new(r"\b([a-zA-Z0-9_-]+)[:-]?(\d+)?\b").unwrap();
+    static ref SINGLE_FILE_REGEX: Regex = Regex::new(r"\b([a-zA-Z0-9_-]+)\b").unwrap();
 }
 
 /// This is a matcher specialized for matching single files (as opposed to paths), particularly
@@ -148,24 +585,28 @@ impl SingleFileMatcher {
 }
 
 impl Matcher for SingleFileMatcher {
-    fn match_line(&self, line: &str) -> Option<MatchResult> {
-        for capture in SINGLE_FILE_REGEX.captures_iter(line) {
-            let word = capture.get(1)?.as_str();
-            if self.cached_single_files.contains(word) {
-                let line_number = capture.get(2).and_then(|m| m.as_str().parse().ok());
-                return Some(MatchResult {
-                    path: word.to_string(),
-                    line_number,
-                });
-            }
-        }
-        None
+    fn match_all(&self, line: &str) -> Vec<LineMatch> {
+        SINGLE_FILE_REGEX
+            .captures_iter(line)
+            .filter_map(|capture| {
+                let word = capture.get(1)?;
+                if !self.cached_single_files.contains(word.as_str()) {
+                    return None;
+                }
+                Some(LineMatch {
+                    span: word.range(),
+                    result: with_position_after(word.as_str().to_string(), line, word.end()),
+                })
+            })
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::matcher::{MatchResult, Matcher, RegexMatcher, SingleFileMatcher};
+    use crate::matcher::{
+        MatchResult, Matcher, RegexMatcher, SingleFileMatcher, select_non_overlapping,
+    };
 
     #[test]
     fn can_match_standard_path_no_line_number() {
@@ -174,7 +615,7 @@ mod tests {
             matcher.match_line("/abc/def/g.e").unwrap(),
             MatchResult {
                 path: String::from("/abc/def/g.e"),
-                line_number: None,
+                ..Default::default()
             }
         );
     }
@@ -187,6 +628,7 @@ mod tests {
             MatchResult {
                 path: String::from("/abc/def/g.e"),
                 line_number: Some(123),
+                ..Default::default()
             }
         );
     }
@@ -198,11 +640,53 @@ mod tests {
             matcher.match_line("/abc/def/g").unwrap(),
             MatchResult {
                 path: String::from("/abc/def/g"),
-                line_number: None,
+                ..Default::default()
             }
         );
     }
 
+    #[test]
+    fn can_match_standard_path_with_line_and_column() {
+        let matcher = RegexMatcher::new();
+        for line in [
+            "src/a.rs:10:5",
+            "src/a.rs(10,5): error C2065: 'x': undeclared identifier",
+            "In src/a.rs, line 10, column 5",
+            r#""src/a.rs": line 10, col 5"#,
+        ] {
+            assert_eq!(
+                matcher.match_line(line).unwrap(),
+                MatchResult {
+                    path: String::from("src/a.rs"),
+                    line_number: Some(10),
+                    column: Some(5),
+                    ..Default::default()
+                },
+                "{line}"
+            );
+        }
+        let result = matcher.match_line("~/a.rs(7) and more").unwrap();
+        assert_eq!((result.line_number, result.column), (Some(7), None));
+    }
+
+    #[test]
+    fn can_match_line_ranges() {
+        let matcher = RegexMatcher::new();
+        for line in ["src/a.rs:10-25", "src/a.rs#L10-L25: the parser"] {
+            assert_eq!(
+                matcher.match_line(line).unwrap(),
+                MatchResult {
+                    path: String::from("src/a.rs"),
+                    line_number: Some(10),
+                    end_line: Some(25),
+                    ..Default::default()
+                },
+                "{line}"
+            );
+        }
+        assert_eq!(matcher.match_line("src/a.rs:25-10").unwrap().end_line, None);
+    }
+
     #[test]
     fn can_match_homedir_default() {
         let matcher = RegexMatcher::new();
@@ -210,7 +694,7 @@ mod tests {
             matcher.match_line("~/a/b/c.rs").unwrap(),
             MatchResult {
                 path: String::from("~/a/b/c.rs"),
-                line_number: None,
+                ..Default::default()
             }
         );
     }
@@ -223,6 +707,7 @@ mod tests {
             MatchResult {
                 path: String::from("~/file.rs"),
                 line_number: Some(42),
+                ..Default::default()
             }
         );
     }
@@ -234,9 +719,63 @@ mod tests {
             matcher.match_line("~/file").unwrap(),
             MatchResult {
                 path: String::from("~/file"),
-                line_number: None,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn can_match_homedir_of_another_user() {
+        let matcher = RegexMatcher::new();
+        assert_eq!(
+            matcher.match_line("see ~alice/notes/todo.md:4").unwrap(),
+            MatchResult {
+                path: String::from("~alice/notes/todo.md"),
+                line_number: Some(4),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn can_match_path_under_environment_variable() {
+        let matcher = RegexMatcher::new();
+        assert_eq!(
+            matcher
+                .match_line("wrote ${XDG_CONFIG_HOME}/rfp/config.toml:3")
+                .unwrap(),
+            MatchResult {
+                path: String::from("${XDG_CONFIG_HOME}/rfp/config.toml"),
+                line_number: Some(3),
+                ..Default::default()
             }
         );
+        assert_eq!(
+            matcher.match_line("$HOME/a/b.rs").unwrap().path,
+            "$HOME/a/b.rs"
+        );
+    }
+
+    #[test]
+    fn matches_every_path_on_a_line() {
+        let matches = select_non_overlapping(
+            RegexMatcher::new().match_all("diff --git a/src/x.rs b/src/y.rs"),
+        );
+        let found: Vec<(&str, std::ops::Range<usize>)> = matches
+            .iter()
+            .map(|m| (m.result.path.as_str(), m.span.clone()))
+            .collect();
+        assert_eq!(found, vec![("src/x.rs", 13..21), ("src/y.rs", 24..32)]);
+    }
+
+    #[test]
+    fn keeps_the_more_specific_of_overlapping_matches() {
+        let matches = RegexMatcher::new().match_all("mv src/a.rs 'My Docs/b.rs' ~/c.txt");
+        let paths: Vec<String> = select_non_overlapping(matches)
+            .into_iter()
+            .map(|m| m.result.path)
+            .collect();
+        assert_eq!(paths, vec!["src/a.rs", "My Docs/b.rs", "~/c.txt"]);
     }
 
     #[test]
@@ -247,6 +786,7 @@ mod tests {
             MatchResult {
                 path: String::from("abc/d/e.rs"),
                 line_number: Some(123),
+                ..Default::default()
             }
         );
     }
@@ -258,7 +798,7 @@ mod tests {
             matcher.match_line("file.rs").unwrap(),
             MatchResult {
                 path: String::from("file.rs"),
-                line_number: None,
+                ..Default::default()
             }
         );
     }
@@ -270,7 +810,7 @@ mod tests {
             matcher.match_line("/file.rs").unwrap(),
             MatchResult {
                 path: String::from("/file.rs"),
-                line_number: None,
+                ..Default::default()
             }
         );
     }
@@ -284,7 +824,7 @@ mod tests {
                 .unwrap(),
             MatchResult {
                 path: String::from("f.l.i.l.e.asomehowsuperduperlongextension"),
-                line_number: None,
+                ..Default::default()
             }
         );
     }
@@ -298,11 +838,208 @@ mod tests {
                 .unwrap(),
             MatchResult {
                 path: String::from("project/file.txt"),
-                line_number: None,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn extra_regexes_take_precedence() {
+        let regex = regex::Regex::new(r"^(?P<path>\S+) \(line (?P<line>\d+)\)").unwrap();
+        let matcher = RegexMatcher::with_extra_regexes(vec![regex]);
+        assert_eq!(
+            matcher.match_line("Makefile (line 12)").unwrap(),
+            MatchResult {
+                path: String::from("Makefile"),
+                line_number: Some(12),
+                ..Default::default()
+            }
+        );
+    }
+
+    fn assert_diagnostic(line: &str, path: &str, line_number: u32, column: u32) {
+        assert_eq!(
+            RegexMatcher::new().match_line(line).unwrap(),
+            MatchResult {
+                path: String::from(path),
+                line_number: Some(line_number),
+                column: Some(column),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn can_match_rustc_diagnostic() {
+        assert_diagnostic("   --> src/lib.rs:10:5", "src/lib.rs", 10, 5);
+        assert_diagnostic(
+            "  --> /home/me/My Project/src/main.rs:2:13",
+            "/home/me/My Project/src/main.rs",
+            2,
+            13,
+        );
+    }
+
+    #[test]
+    fn can_match_gcc_and_clang_diagnostic() {
+        assert_diagnostic(
+            "src/a.c:10:5: error: expected ';' after expression",
+            "src/a.c",
+            10,
+            5,
+        );
+        assert_diagnostic(
+            "  src/main.rs:10:5: error: mismatched types",
+            "src/main.rs",
+            10,
+            5,
+        );
+        // The prose before the path isn't part of it.
+        assert_diagnostic(
+            "In file included from src/main.rs:3:1: note: here",
+            "src/main.rs",
+            3,
+            1,
+        );
+    }
+
+    #[test]
+    fn can_match_tsc_diagnostic() {
+        assert_diagnostic(
+            "src/a.ts(10,5): error TS2322: Type 'string' is not assignable to type 'number'.",
+            "src/a.ts",
+            10,
+            5,
+        );
+        assert_diagnostic(
+            "  src/b.ts(2,1): error TS1005: ';' expected.",
+            "src/b.ts",
+            2,
+            1,
+        );
+    }
+
+    #[test]
+    fn can_match_msbuild_diagnostic() {
+        assert_diagnostic(
+            r"  1>C:\src\App\File.cs(10,5,10,9): error CS1002: ; expected [C:\src\App\App.csproj]",
+            r"C:\src\App\File.cs",
+            10,
+            5,
+        );
+        assert_diagnostic(
+            "File.cs(7,12): warning CS0168: The variable 'e' is declared but never used",
+            "File.cs",
+            7,
+            12,
+        );
+    }
+
+    #[test]
+    fn can_match_double_quoted_path_with_spaces() {
+        let matcher = RegexMatcher::new();
+        assert_eq!(
+            matcher
+                .match_line(r#"error: cannot open "My Docs/notes.txt":12"#)
+                .unwrap(),
+            MatchResult {
+                path: String::from("My Docs/notes.txt"),
+                line_number: Some(12),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn can_match_single_quoted_path_with_spaces() {
+        let matcher = RegexMatcher::new();
+        assert_eq!(
+            matcher.match_line("rm 'Application Support/a.db'").unwrap(),
+            MatchResult {
+                path: String::from("Application Support/a.db"),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn can_match_backtick_quoted_path_with_spaces() {
+        let matcher = RegexMatcher::new();
+        assert_eq!(
+            matcher.match_line("see `gen assets/logo 2x.png`").unwrap(),
+            MatchResult {
+                path: String::from("gen assets/logo 2x.png"),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn ignores_quoted_text_that_is_not_a_path() {
+        let matcher = RegexMatcher::new();
+        assert_eq!(
+            matcher.match_line(r#"src/a.rs: "hello world""#).unwrap(),
+            MatchResult {
+                path: String::from("src/a.rs"),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn can_match_path_with_escaped_spaces() {
+        let matcher = RegexMatcher::new();
+        assert_eq!(
+            matcher.match_line(r"open ~/My\ Docs/a\ b.txt:7:2").unwrap(),
+            MatchResult {
+                path: String::from("~/My Docs/a b.txt"),
+                line_number: Some(7),
+                column: Some(2),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn can_match_unquoted_path_with_spaces_that_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("My Docs")).unwrap();
+        std::fs::write(dir.path().join("My Docs/a file.txt"), "").unwrap();
+        let path = dir
+            .path()
+            .join("My Docs/a file.txt")
+            .to_string_lossy()
+            .to_string();
+
+        let matcher = RegexMatcher::new();
+        let result = matcher.match_line(&format!("M  {path}:3: changed"));
+        assert_eq!(
+            result.unwrap(),
+            MatchResult {
+                path,
+                line_number: Some(3),
+                ..Default::default()
             }
         );
     }
 
+    #[test]
+    fn only_widens_into_directories_that_exist() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/a b.txt"), "").unwrap();
+        let path = dir
+            .path()
+            .join("docs/a b.txt")
+            .to_string_lossy()
+            .to_string();
+
+        let matcher = RegexMatcher::new();
+        let found = matcher.match_line(&format!("see {path} here"));
+        assert!(!matcher.spaced_dir_exists("see docs/a"));
+        assert_eq!(found.map(|r| r.path), Some(path));
+    }
+
     /// Assuming cwd is at the root of the project, which seems to be an invariant.
     #[test]
     fn can_match_single_extensionless_file_in_the_directory() {
@@ -313,7 +1050,7 @@ mod tests {
                 .unwrap(),
             MatchResult {
                 path: String::from("LICENSE"),
-                line_number: None,
+                ..Default::default()
             }
         );
     }
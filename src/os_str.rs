@@ -0,0 +1,136 @@
+//! Carries bytes that aren't valid UTF-8, such as odd filenames from `find`, through the
+//! `String`-based matching without losing them.
+//!
+//! Each invalid byte is decoded to a character of the Supplementary Private Use Area-B, U+10FF00
+//! plus the byte, and encoded back to the byte when the path is handed to the OS. Those characters
+//! are shown as U+FFFD. The input's own characters of that range are escaped byte by byte as well,
+//! so that they aren't taken for escaped bytes.
+
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+
+const ESCAPE_BASE: u32 = 0x10ff00;
+
+/// Decodes `bytes`, escaping the invalid ones.
+pub fn decode(bytes: &[u8]) -> Cow<'_, str> {
+    if let Ok(s) = std::str::from_utf8(bytes)
+        && !s.chars().any(|c| unescape(c).is_some())
+    {
+        return Cow::Borrowed(s);
+    }
+    let mut decoded = String::with_capacity(bytes.len() + 8);
+    let mut buf = [0; 4];
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if unescape(c).is_some() {
+                decoded.extend(c.encode_utf8(&mut buf).bytes().map(escape));
+            } else {
+                decoded.push(c);
+            }
+        }
+        decoded.extend(chunk.invalid().iter().map(|&b| escape(b)));
+    }
+    Cow::Owned(decoded)
+}
+
+/// Encodes `s` back to the bytes it was decoded from.
+pub fn encode(s: &str) -> Cow<'_, [u8]> {
+    if !s.chars().any(|c| unescape(c).is_some()) {
+        return Cow::Borrowed(s.as_bytes());
+    }
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut buf = [0; 4];
+    for c in s.chars() {
+        match unescape(c) {
+            Some(b) => bytes.push(b),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+        }
+    }
+    Cow::Owned(bytes)
+}
+
+#[cfg(unix)]
+pub fn to_os_string(s: &str) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+
+    OsString::from_vec(encode(s).into_owned())
+}
+
+/// Paths are UTF-16 on Windows, so bytes that weren't valid UTF-8 can't have come from one.
+#[cfg(not(unix))]
+pub fn to_os_string(s: &str) -> OsString {
+    OsString::from(display(s).into_owned())
+}
+
+/// Decodes a path from the OS, the other way around from [`to_os_string`].
+#[cfg(unix)]
+pub fn decode_os_str(s: &OsStr) -> Cow<'_, str> {
+    use std::os::unix::ffi::OsStrExt;
+
+    decode(s.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn decode_os_str(s: &OsStr) -> Cow<'_, str> {
+    s.to_string_lossy()
+}
+
+/// `s` with escaped bytes replaced by U+FFFD, one character for one.
+pub fn display(s: &str) -> Cow<'_, str> {
+    if !s.chars().any(|c| unescape(c).is_some()) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(
+        s.chars()
+            .map(|c| {
+                if unescape(c).is_some() {
+                    char::REPLACEMENT_CHARACTER
+                } else {
+                    c
+                }
+            })
+            .collect(),
+    )
+}
+
+fn escape(byte: u8) -> char {
+    char::from_u32(ESCAPE_BASE + byte as u32).expect("U+10FF80..U+10FFFF are valid characters")
+}
+
+fn unescape(c: char) -> Option<u8> {
+    // Only bytes >= 0x80 can be invalid.
+    let c = c as u32;
+    (ESCAPE_BASE + 0x80..=ESCAPE_BASE + 0xff)
+        .contains(&c)
+        .then(|| (c - ESCAPE_BASE) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::os_str::{decode, display, encode};
+
+    #[test]
+    fn round_trips_invalid_bytes() {
+        let bytes = b"caf\xe9/na\xefve \xe2\x9c\x93.txt";
+        let decoded = decode(bytes);
+        assert_eq!(display(&decoded), "caf\u{fffd}/na\u{fffd}ve \u{2713}.txt");
+        assert_eq!(encode(&decoded).as_ref(), bytes);
+    }
+
+    #[test]
+    fn round_trips_characters_that_escapes_are_made_of() {
+        let bytes = "a\u{10ff9b}\u{10ffff}.txt".as_bytes();
+        let decoded = decode(bytes);
+        assert_eq!(display(&decoded), format!("a{}.txt", "\u{fffd}".repeat(8)));
+        assert_eq!(encode(&decoded).as_ref(), bytes);
+    }
+
+    #[test]
+    fn borrows_valid_utf8() {
+        assert!(matches!(
+            decode("a/é.rs".as_bytes()),
+            std::borrow::Cow::Borrowed(_)
+        ));
+        assert!(matches!(encode("a/é.rs"), std::borrow::Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,147 @@
+//! Writes the selection to stdout for scripting, e.g. `git status | rfp --print | xargs git add`.
+
+use crate::matcher::MatchResult;
+use crate::os_str;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// One path per line.
+    #[default]
+    Newline,
+    /// NUL-terminated paths, for `xargs -0` and friends.
+    Null,
+    /// One JSON object per line, with the path and its position: the line, column and the last
+    /// line of a range.
+    JsonLines,
+}
+
+/// Writes `selections` in `format`. With `with_position`, plain paths are suffixed with their
+/// position, e.g. `src/main.rs:12` or `src/main.rs:12:5`. JSON lines always include the position.
+pub fn write_selection(
+    writer: &mut impl Write,
+    selections: &[MatchResult],
+    format: OutputFormat,
+    with_position: bool,
+) -> io::Result<()> {
+    for selection in selections {
+        match format {
+            OutputFormat::Newline | OutputFormat::Null => {
+                writer.write_all(&os_str::encode(&selection.expanded_path()))?;
+                if with_position && let Some(line) = selection.line_number {
+                    write!(writer, ":{line}")?;
+                    if let Some(column) = selection.column {
+                        write!(writer, ":{column}")?;
+                    }
+                }
+                let terminator = if format == OutputFormat::Null {
+                    "\0"
+                } else {
+                    "\n"
+                };
+                write!(writer, "{terminator}")?;
+            }
+            OutputFormat::JsonLines => {
+                let number = |n: Option<u32>| n.map_or("null".to_string(), |n| n.to_string());
+                writeln!(
+                    writer,
+                    r#"{{"path":{},"line":{},"column":{},"end_line":{}}}"#,
+                    json_string(&os_str::display(&selection.expanded_path())),
+                    number(selection.line_number),
+                    number(selection.column),
+                    number(selection.end_line),
+                )?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matcher::MatchResult;
+    use crate::output::{OutputFormat, write_selection};
+
+    fn render(format: OutputFormat, with_position: bool) -> String {
+        let selections = vec![
+            MatchResult {
+                path: String::from("src/a.rs"),
+                line_number: Some(3),
+                ..Default::default()
+            },
+            MatchResult {
+                path: String::from("b \"quoted\".txt"),
+                ..Default::default()
+            },
+            MatchResult {
+                path: String::from("c.rs"),
+                line_number: Some(10),
+                column: Some(5),
+                end_line: Some(12),
+                ..Default::default()
+            },
+        ];
+        let mut out = vec![];
+        write_selection(&mut out, &selections, format, with_position).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn writes_newline_separated_paths() {
+        assert_eq!(
+            render(OutputFormat::Newline, false),
+            "src/a.rs\nb \"quoted\".txt\nc.rs\n"
+        );
+    }
+
+    #[test]
+    fn writes_nul_separated_paths_with_positions() {
+        assert_eq!(
+            render(OutputFormat::Null, true),
+            "src/a.rs:3\0b \"quoted\".txt\0c.rs:10:5\0"
+        );
+    }
+
+    #[test]
+    fn prints_where_paths_were_resolved_to() {
+        let resolved = MatchResult {
+            path: String::from("src/a.rs"),
+            line_number: Some(3),
+            resolved_path: Some(String::from("../src/a.rs")),
+            ..Default::default()
+        };
+        let mut out = vec![];
+        write_selection(&mut out, &[resolved], OutputFormat::Newline, true).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "../src/a.rs:3\n");
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        assert_eq!(
+            render(OutputFormat::JsonLines, false),
+            concat!(
+                "{\"path\":\"src/a.rs\",\"line\":3,\"column\":null,\"end_line\":null}\n",
+                "{\"path\":\"b \\\"quoted\\\".txt\",\"line\":null,\"column\":null,\"end_line\":null}\n",
+                "{\"path\":\"c.rs\",\"line\":10,\"column\":5,\"end_line\":12}\n",
+            )
+        );
+    }
+}
@@ -1,65 +1,399 @@
-use crate::matcher::{Matcher, RegexMatcher, SingleFileMatcher};
+use crate::ansi::Sanitized;
+use crate::git::GitStatusMatcher;
+use crate::matcher::{
+    LineMatch, MatchResult, RegexMatcher, SingleFileMatcher, StreamMatcher, WINDOW_LINES, Window,
+    looks_like_path, select_non_overlapping,
+};
+use crate::range::{DiffHunkMatcher, PermalinkMatcher};
+use crate::resolve::Resolver;
+use crate::stack_trace::{
+    GoTraceMatcher, JavaTraceMatcher, NodeTraceMatcher, PythonTraceMatcher, RustPanicMatcher,
+};
+use crate::{ansi, os_str};
+use globset::GlobSet;
 use log::debug;
-use std::collections::HashSet;
+use ratatui::style::Style;
+use regex::Regex;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{self, BufRead, BufReader};
-// Bruh I don't like it that rust-analyzer isn't smart enough for conditionally compiled test logic.
-use std::path::Path;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
-pub fn run() -> io::Result<Vec<String>> {
-    let lines = process_pipe_input();
-    run_with_input(lines?)
+/// A line counts as binary when more than one in this many of its bytes is NUL.
+const BINARY_NUL_RATIO: usize = 8;
+
+/// How the input is matched.
+#[derive(Default)]
+pub struct PipeOptions {
+    /// Regexes tried before the built-in ones. See [`RegexMatcher::with_extra_regexes`].
+    pub extra_regexes: Vec<Regex>,
+    /// Candidates whose path matches are dropped.
+    pub ignore: GlobSet,
+    /// Split the input on NUL bytes instead of newlines, and take every record as a path without
+    /// matching it. Detected from the start of the input when not set.
+    pub null_delimited: bool,
+    /// Keep a candidate for every line of a path rather than for the path, e.g. for each grep hit.
+    pub per_line: bool,
+    /// Where paths that aren't relative to the cwd are looked up. See [`Resolver::new`].
+    pub roots: Vec<PathBuf>,
+    /// Prefixes stripped from absolute paths that don't exist, such as a CI build directory.
+    pub strip_prefixes: Vec<Regex>,
+}
+
+/// The input line a candidate was found on.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Source {
+    /// The line with its escape sequences stripped.
+    pub text: String,
+    /// See [`ansi::Sanitized::styles`].
+    pub styles: Vec<(Range<usize>, Style)>,
+    /// The byte range of the path in `text`.
+    pub span: Range<usize>,
+}
+
+/// A path found in the input.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Candidate {
+    pub result: MatchResult,
+    /// The line it was first found on. NUL-delimited paths don't come from one.
+    pub source: Option<Source>,
+}
+
+/// What the input yields, in order of appearance.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Found {
+    Candidate(Candidate),
+    /// The candidate found in the given position, 0 being the first, showed up on another line.
+    Again(usize, Source),
+    /// The candidate found in the given position, which came without a line, showed up with one,
+    /// e.g. a file named in a diff header and then positioned by its first hunk. See
+    /// [`MatchResult::take_position`].
+    Positioned(usize, MatchResult),
 }
 
-fn run_with_input(lines: Vec<String>) -> io::Result<Vec<String>> {
-    let matchers: Vec<Box<dyn Matcher>> = vec![
-        Box::new(RegexMatcher::new()),
+/// How many candidates may wait in the channel. Reading stops once it's full, e.g. while the
+/// editor is open, rather than keeping everything that's still coming in memory.
+const CHANNEL_BOUND: usize = 1024;
+
+/// Reads stdin on a background thread and streams candidates through the returned channel as soon
+/// as they are matched, so that the selection can start before the input is exhausted. The channel
+/// disconnects once the input has been fully consumed.
+pub fn run(mut options: PipeOptions) -> Receiver<io::Result<Found>> {
+    let (tx, rx) = mpsc::sync_channel(CHANNEL_BOUND);
+    thread::spawn(move || {
+        let mut reader = BufReader::new(io::stdin().lock());
+        if !options.null_delimited && reader.fill_buf().is_ok_and(looks_null_delimited) {
+            debug!("Reading the input as NUL-delimited paths");
+            options.null_delimited = true;
+        }
+        let delimiter = if options.null_delimited { b'\0' } else { b'\n' };
+        let matchers = matchers(&options);
+        if let Err(e) = run_with_input(reader.split(delimiter), matchers, &options, |m| {
+            tx.send(Ok(m)).is_ok()
+        }) {
+            let _ = tx.send(Err(e));
+        }
+    });
+    rx
+}
+
+/// The matchers for `options`, in order of trust.
+fn matchers(options: &PipeOptions) -> Vec<Box<dyn StreamMatcher>> {
+    // Stack trace frames, ranges and git status entries come first so that what they tell of the
+    // path wins over the generic matches of it.
+    vec![
+        Box::new(PythonTraceMatcher),
+        Box::new(JavaTraceMatcher::new()),
+        Box::new(GoTraceMatcher),
+        Box::new(NodeTraceMatcher),
+        Box::new(RustPanicMatcher),
+        Box::new(DiffHunkMatcher::default()),
+        Box::new(PermalinkMatcher),
+        Box::new(GitStatusMatcher::default()),
+        Box::new(RegexMatcher::with_extra_regexes(
+            options.extra_regexes.clone(),
+        )),
         Box::new(SingleFileMatcher::new()),
-    ];
-    let mut matches = vec![];
-    let mut seen: HashSet<String> = HashSet::new();
-
-    for line in &lines {
-        for matcher in &matchers {
-            if let Some(match_result) = matcher.match_line(line) {
-                debug!(
-                    "Matched: {} on line {:?}",
-                    match_result.path, match_result.line_number
-                );
-                if file_exists(&match_result.path) && !seen.contains(&match_result.path) {
-                    seen.insert(match_result.path.clone());
-                    matches.push(match_result.path);
+    ]
+}
+
+/// Matches `records`, lines or NUL-delimited paths, one by one and hands every new candidate, or
+/// new line of a known one, to `emit` in order of appearance, stopping early once `emit` returns
+/// false.
+fn run_with_input(
+    records: impl Iterator<Item = io::Result<Vec<u8>>>,
+    mut matchers: Vec<Box<dyn StreamMatcher>>,
+    options: &PipeOptions,
+    mut emit: impl FnMut(Found) -> bool,
+) -> io::Result<()> {
+    let lookahead = matchers
+        .iter()
+        .map(|m| m.lookahead().min(WINDOW_LINES))
+        .max()
+        .unwrap_or(0);
+    let resolver = Resolver::new(options.roots.clone(), options.strip_prefixes.clone());
+    // The position of every candidate, by path and, in per-line mode, line number.
+    let mut seen: HashMap<(String, Option<u32>), usize> = HashMap::new();
+    // The candidates that have yet to be found with a line.
+    let mut unpositioned: HashSet<usize> = HashSet::new();
+    // Hands the new candidates over, and tells whether to go on.
+    let mut emit_new = |candidates: Vec<Candidate>| {
+        for candidate in candidates {
+            let count = seen.len();
+            let result = &candidate.result;
+            let line_number = result.line_number.filter(|_| options.per_line);
+            let has_position = result.line_number.is_some() || result.end_line.is_some();
+            let found = match seen.entry((result.expanded_path().into_owned(), line_number)) {
+                Entry::Occupied(entry) => {
+                    let index = *entry.get();
+                    if has_position
+                        && unpositioned.remove(&index)
+                        && !emit(Found::Positioned(index, candidate.result))
+                    {
+                        return false;
+                    }
+                    match candidate.source {
+                        Some(source) => Found::Again(index, source),
+                        None => continue,
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(count);
+                    if !has_position {
+                        unpositioned.insert(count);
+                    }
+                    Found::Candidate(candidate)
                 }
+            };
+            if !emit(found) {
+                return false;
             }
         }
+        true
+    };
+    // The lines read around the next one to match, `lines[current]`.
+    let mut lines: VecDeque<Sanitized> = VecDeque::new();
+    let mut current = 0;
+
+    for record in records {
+        let record = record?;
+        if options.null_delimited {
+            // The record is a path as is, which may well contain newlines or look like a position.
+            if record.is_empty() {
+                continue;
+            }
+            let result = MatchResult {
+                path: os_str::decode(&record).into_owned(),
+                line_number: None,
+                column: None,
+                end_line: None,
+                git_status: None,
+                resolved_path: None,
+            };
+            if let Some(result) = resolve(result, options, &resolver)
+                && !emit_new(vec![Candidate {
+                    result,
+                    source: None,
+                }])
+            {
+                return Ok(());
+            }
+            continue;
+        }
+        let Some(line) = sanitize(&record) else {
+            continue;
+        };
+        lines.push_back(line);
+        if lines.len() - current > lookahead {
+            let candidates = match_line(&mut matchers, &lines, current, options, &resolver);
+            if !emit_new(candidates) {
+                return Ok(());
+            }
+            current += 1;
+            if current > WINDOW_LINES {
+                lines.pop_front();
+                current -= 1;
+            }
+        }
+    }
+    while current < lines.len() {
+        if !emit_new(match_line(
+            &mut matchers,
+            &lines,
+            current,
+            options,
+            &resolver,
+        )) {
+            return Ok(());
+        }
+        current += 1;
     }
-    Ok(matches)
+    Ok(())
 }
 
-#[cfg(not(test))]
-fn file_exists(path: &str) -> bool {
-    Path::new(path).try_exists().unwrap_or(false)
+/// `line` with its escape sequences stripped, unless it's binary data.
+fn sanitize(line: &[u8]) -> Option<Sanitized> {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if is_binary(line) {
+        debug!("Skipped a binary line of {} bytes", line.len());
+        return None;
+    }
+    Some(ansi::sanitize(&os_str::decode(line)))
 }
 
-#[cfg(test)]
-fn file_exists(_path: &str) -> bool {
-    true
+/// Finds the wanted paths on `lines[current]`, from left to right.
+fn match_line(
+    matchers: &mut [Box<dyn StreamMatcher>],
+    lines: &VecDeque<Sanitized>,
+    current: usize,
+    options: &PipeOptions,
+    resolver: &Resolver,
+) -> Vec<Candidate> {
+    let texts: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+    let window = Window::new(&texts, current);
+    let line = &lines[current];
+    // Hyperlink targets come last, so that a link whose text already names the file keeps the
+    // position found in the text.
+    let links = line.links.iter().filter_map(|link| {
+        Some(LineMatch {
+            span: link.span.clone(),
+            result: MatchResult {
+                path: link.file_path()?,
+                line_number: None,
+                column: None,
+                end_line: None,
+                git_status: None,
+                resolved_path: None,
+            },
+        })
+    });
+    // Paths that don't exist or are ignored are dropped before resolving overlaps, so that they
+    // can't shadow a less trusted match that does exist.
+    let matches = matchers
+        .iter_mut()
+        .flat_map(|matcher| matcher.match_window(&window))
+        .chain(links)
+        .filter_map(|m| {
+            debug!(
+                "Matched: {} on line {:?}",
+                m.result.path, m.result.line_number
+            );
+            Some(LineMatch {
+                result: resolve(m.result, options, resolver)?,
+                ..m
+            })
+        });
+    select_non_overlapping(matches)
+        .into_iter()
+        .map(|m| {
+            let source = Source {
+                text: line.text.clone(),
+                styles: line.styles.clone(),
+                span: m.span,
+            };
+            Candidate {
+                result: m.result,
+                source: Some(source),
+            }
+        })
+        .collect()
 }
 
-fn process_pipe_input() -> io::Result<Vec<String>> {
-    let stdin = io::stdin();
-    // TODO: come back to double check the perf for large input
-    let reader = BufReader::new(stdin.lock());
-    reader.lines().collect()
+/// `result` if it names an existing path that isn't ignored, with the path it was found at when
+/// it isn't relative to the cwd. Paths `git status` lists as deleted are kept too, e.g. to restore
+/// them.
+fn resolve(
+    mut result: MatchResult,
+    options: &PipeOptions,
+    resolver: &Resolver,
+) -> Option<MatchResult> {
+    let is_ignored = |result: &MatchResult| {
+        let path = result.fs_path();
+        let is_ignored = options.ignore.is_match(&path);
+        if is_ignored {
+            debug!("Ignored: {}", path.display());
+        }
+        is_ignored
+    };
+    if is_ignored(&result) {
+        return None;
+    }
+    if !file_exists(&result.fs_path()) {
+        if let Some(path) = resolver.resolve(&result.expanded_path()) {
+            debug!("Resolved {} to {path}", result.path);
+            result.resolved_path = Some(path);
+            if is_ignored(&result) {
+                return None;
+            }
+        } else if !result.is_deleted() || !looks_like_path(&result.path) {
+            // Only paths that look like one, as a line such as ` D is for dog` reads as a
+            // deletion too.
+            return None;
+        }
+    }
+    Some(result)
+}
+
+/// `find -print0`, `git ls-files -z` and `rg -l --null` end every path with a NUL. `rg --null`
+/// without `-l` keeps a newline per line, and binary data has empty records and control bytes.
+fn looks_null_delimited(sample: &[u8]) -> bool {
+    let count = |byte: u8| sample.iter().filter(|&&b| b == byte).count();
+    if count(0) <= count(b'\n') {
+        return false;
+    }
+    // The last record may be cut short by the end of the sample.
+    let mut records: Vec<&[u8]> = sample.split(|&b| b == 0).collect();
+    records.pop();
+    records.iter().all(|record| {
+        !record.is_empty()
+            && record
+                .iter()
+                .all(|&b| !b.is_ascii_control() || b == b'\n' || b == b'\t')
+    })
+}
+
+/// Lines that are mostly NUL bytes come from binary data rather than text.
+fn is_binary(line: &[u8]) -> bool {
+    line.iter().filter(|&&b| b == 0).count() * BINARY_NUL_RATIO > line.len()
+}
+
+#[cfg(not(test))]
+fn file_exists(path: &Path) -> bool {
+    path.try_exists().unwrap_or(false)
+}
+
+/// Everything but what's under `missing/` exists in tests.
+#[cfg(test)]
+fn file_exists(path: &Path) -> bool {
+    !path.starts_with("missing")
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::pipe::run_with_input;
+    use crate::matcher::{LineMatch, MatchResult, StreamMatcher, Window};
+    use crate::os_str;
+    use crate::pipe::{
+        Candidate, Found, PipeOptions, Source, looks_null_delimited, matchers, run_with_input,
+    };
 
     fn assert_helper(input: Vec<&str>, expected: Vec<&str>) {
-        let res = run_with_input(input.iter().map(|s| s.to_string()).collect());
+        assert_helper_with_options(input, expected, &PipeOptions::default());
+    }
+
+    fn assert_helper_with_options(input: Vec<&str>, expected: Vec<&str>, options: &PipeOptions) {
+        let input = input.iter().map(|s| s.as_bytes()).collect();
+        let paths: Vec<String> = find(input, options)
+            .into_iter()
+            .filter_map(|found| match found {
+                Found::Candidate(candidate) => Some(candidate.result.path),
+                Found::Again(..) | Found::Positioned(..) => None,
+            })
+            .collect();
         let expected_strings: Vec<String> = expected.iter().map(|s| s.to_string()).collect();
-        assert_eq!(res.unwrap(), expected_strings);
+        assert_eq!(paths, expected_strings);
     }
 
     #[test]
@@ -67,6 +401,48 @@ mod tests {
         assert_helper(vec!["abc/d/e.rs", "abc/d/e.rs"], vec!["abc/d/e.rs"]);
     }
 
+    fn find(input: Vec<&[u8]>, options: &PipeOptions) -> Vec<Found> {
+        let mut found = vec![];
+        let records = input.into_iter().map(|b| Ok(b.to_vec()));
+        run_with_input(records, matchers(options), options, |f| {
+            found.push(f);
+            true
+        })
+        .unwrap();
+        found
+    }
+
+    #[test]
+    fn reports_the_lines_candidates_are_found_on() {
+        let found = find(
+            vec![b"src/a.rs:3: one", b"see src/b.rs and src/a.rs"],
+            &PipeOptions::default(),
+        );
+        let source = |text: &str, span| Source {
+            text: String::from(text),
+            styles: vec![],
+            span,
+        };
+        let candidate = |path: &str, line_number, source| {
+            Found::Candidate(Candidate {
+                result: MatchResult {
+                    path: String::from(path),
+                    line_number,
+                    ..Default::default()
+                },
+                source: Some(source),
+            })
+        };
+        assert_eq!(
+            found,
+            vec![
+                candidate("src/a.rs", Some(3), source("src/a.rs:3: one", 0..8)),
+                candidate("src/b.rs", None, source("see src/b.rs and src/a.rs", 4..12)),
+                Found::Again(0, source("see src/b.rs and src/a.rs", 17..25)),
+            ]
+        );
+    }
+
     #[test]
     fn order_is_retained() {
         assert_helper(
@@ -74,4 +450,187 @@ mod tests {
             vec!["abc/c.rs", "abc/b.rs", "abc/a.rs"],
         );
     }
+
+    #[test]
+    fn collects_every_path_on_a_line_in_order() {
+        assert_helper(
+            vec!["mv src/b.rs src/a.rs", "diff a/src/c.rs b/src/a.rs"],
+            vec!["src/b.rs", "src/a.rs", "src/c.rs"],
+        );
+    }
+
+    #[test]
+    fn positions_paths_found_without_a_line_at_the_first_one_they_come_with() {
+        let found = find(
+            vec![
+                b"diff --git a/src/a.rs b/src/a.rs",
+                b"--- a/src/a.rs",
+                b"+++ b/src/a.rs",
+                b"@@ -10,7 +12,9 @@ fn main() {",
+                b"@@ -40 +42 @@",
+            ],
+            &PipeOptions::default(),
+        );
+        let positioned: Vec<&MatchResult> = found
+            .iter()
+            .filter_map(|found| match found {
+                Found::Positioned(0, result) => Some(result),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            positioned,
+            vec![&MatchResult {
+                path: String::from("src/a.rs"),
+                line_number: Some(12),
+                end_line: Some(20),
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_existing_paths_overlapped_by_missing_ones() {
+        assert_helper(
+            vec![
+                "see 'missing/x src/a.rs' for details",
+                "src/main.rs src/pipe.rs:10:5: warning: x",
+            ],
+            vec!["src/a.rs", "src/main.rs", "src/pipe.rs"],
+        );
+    }
+
+    #[test]
+    fn keeps_a_candidate_per_line_in_per_line_mode() {
+        let input = vec![
+            "src/a.rs:3: one",
+            "src/a.rs:9: two",
+            "src/a.rs:3: one again",
+        ];
+        assert_helper(input.clone(), vec!["src/a.rs"]);
+        let options = PipeOptions {
+            per_line: true,
+            ..PipeOptions::default()
+        };
+        let input = input.iter().map(|s| s.as_bytes()).collect();
+        let found: Vec<(usize, Option<u32>)> = find(input, &options)
+            .into_iter()
+            .enumerate()
+            .map(|(i, found)| match found {
+                Found::Candidate(candidate) => (i, candidate.result.line_number),
+                Found::Again(candidate, _) | Found::Positioned(candidate, _) => (candidate, None),
+            })
+            .collect();
+        assert_eq!(found, vec![(0, Some(3)), (1, Some(9)), (0, None)]);
+    }
+
+    /// Matches `@@` on the line that names a path after it, `n` lines down, and checks what it
+    /// sees of the previous line.
+    struct Lookahead(usize);
+
+    impl StreamMatcher for Lookahead {
+        fn lookahead(&self) -> usize {
+            self.0
+        }
+
+        fn match_window(&mut self, window: &Window) -> Vec<LineMatch> {
+            let Some(path) = window.after(self.0).filter(|_| window.current() == "@@") else {
+                return vec![];
+            };
+            assert_eq!(window.before(1), Some("before"));
+            vec![LineMatch {
+                span: 0..2,
+                result: MatchResult {
+                    path: String::from(path),
+                    ..Default::default()
+                },
+            }]
+        }
+    }
+
+    #[test]
+    fn lets_stream_matchers_see_the_lines_around() {
+        let lines = ["before", "@@", "x", "a.rs", "b.rs"];
+        let records = lines.iter().map(|s| Ok(s.as_bytes().to_vec()));
+        let matchers: Vec<Box<dyn StreamMatcher>> = vec![Box::new(Lookahead(2))];
+        let mut paths = vec![];
+        run_with_input(records, matchers, &PipeOptions::default(), |found| {
+            if let Found::Candidate(candidate) = found {
+                paths.push(candidate.result.path);
+            }
+            true
+        })
+        .unwrap();
+        assert_eq!(paths, vec!["a.rs"]);
+    }
+
+    #[test]
+    fn matches_colored_and_hyperlinked_input() {
+        assert_helper(
+            vec![
+                "\x1b[35msrc/a.rs\x1b[0m:\x1b[32m3\x1b[0m:fn a()",
+                "\x1b]8;;file://host/abs/b.txt\x1b\\b\x1b]8;;\x1b\\",
+            ],
+            vec!["src/a.rs", "/abs/b.txt"],
+        );
+    }
+
+    #[test]
+    fn keeps_invalid_utf8_and_skips_binary_lines() {
+        let found = find(
+            vec![b"src/caf\xe9.rs:3\r", b"\0\0\0\0src/a.rs\0\0\0\0"],
+            &PipeOptions::default(),
+        );
+        let [Found::Candidate(candidate)] = found.as_slice() else {
+            panic!("Expected one candidate, got {found:?}");
+        };
+        assert_eq!(
+            os_str::encode(&candidate.result.path).as_ref(),
+            b"src/caf\xe9.rs"
+        );
+    }
+
+    #[test]
+    fn takes_null_delimited_records_as_paths() {
+        let options = PipeOptions {
+            null_delimited: true,
+            ..PipeOptions::default()
+        };
+        assert_helper_with_options(
+            vec!["./a\nb.txt", "", "src/main.rs:12", "src/main.rs:12"],
+            vec!["./a\nb.txt", "src/main.rs:12"],
+            &options,
+        );
+    }
+
+    #[test]
+    fn detects_null_delimited_input() {
+        assert!(looks_null_delimited(
+            b"./a.rs\0./b c.rs\0./d\ne.rs\0./trunc"
+        ));
+        // rg --null, which only replaces the colon after the path
+        assert!(!looks_null_delimited(
+            b"a.rs\x0012:fn a()\nb.rs\x003:fn b()\n"
+        ));
+        // binary data
+        assert!(!looks_null_delimited(
+            b"\x7fELF\x02\x01\x01\0\0\0\0/lib/ld.so\0"
+        ));
+        assert!(!looks_null_delimited(b"src/a.rs\nsrc/b.rs\n"));
+    }
+
+    #[test]
+    fn drops_ignored_paths() {
+        let mut ignore = globset::GlobSetBuilder::new();
+        ignore.add(globset::Glob::new("target/**").unwrap());
+        let options = PipeOptions {
+            ignore: ignore.build().unwrap(),
+            ..PipeOptions::default()
+        };
+        assert_helper_with_options(
+            vec!["target/debug/build.rs", "src/main.rs"],
+            vec!["src/main.rs"],
+            &options,
+        );
+    }
 }
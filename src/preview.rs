@@ -0,0 +1,125 @@
+//! Loads what's shown in the TUI preview pane for a candidate.
+//!
+//! Only the visible window of a file is read, so previewing a match deep into a huge log stays
+//! cheap.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// How many leading bytes are sniffed to tell binary files apart from text.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PreviewContent {
+    /// Lines `first_line..first_line + lines.len()` of a text file (0-based).
+    Text {
+        first_line: usize,
+        lines: Vec<String>,
+    },
+    Binary,
+    /// Sorted entry names, with a trailing `/` for directories.
+    Directory(Vec<String>),
+    Unavailable(String),
+}
+
+/// Loads at most `max_lines` lines of `path` starting at the 0-based `first_line`.
+pub fn load(path: &Path, first_line: usize, max_lines: usize) -> PreviewContent {
+    if path.is_dir() {
+        return match list_directory(path) {
+            Ok(entries) => PreviewContent::Directory(entries),
+            Err(e) => PreviewContent::Unavailable(e.to_string()),
+        };
+    }
+    match read_window(path, first_line, max_lines) {
+        Ok(content) => content,
+        Err(e) => PreviewContent::Unavailable(e.to_string()),
+    }
+}
+
+fn list_directory(path: &Path) -> io::Result<Vec<String>> {
+    let mut entries: Vec<String> = fs::read_dir(path)?
+        .filter_map(|res| res.ok())
+        .map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            if e.path().is_dir() {
+                format!("{name}/")
+            } else {
+                name
+            }
+        })
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+fn read_window(path: &Path, first_line: usize, max_lines: usize) -> io::Result<PreviewContent> {
+    let mut head = Vec::with_capacity(BINARY_SNIFF_LEN);
+    File::open(path)?
+        .take(BINARY_SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+    if head.contains(&0) {
+        return Ok(PreviewContent::Binary);
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = vec![];
+    let mut lines = vec![];
+    let mut line_idx = 0;
+    while lines.len() < max_lines {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        if line_idx >= first_line {
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\n', '\r']);
+            lines.push(line.replace('\t', &" ".repeat(TAB_WIDTH)));
+        }
+        line_idx += 1;
+    }
+    Ok(PreviewContent::Text { first_line, lines })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::preview::{PreviewContent, load};
+
+    #[test]
+    fn loads_requested_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "one\ntwo\n\tthree\nfour\n").unwrap();
+        let content = load(&file, 1, 2);
+        assert_eq!(
+            content,
+            PreviewContent::Text {
+                first_line: 1,
+                lines: vec![String::from("two"), String::from("    three")],
+            }
+        );
+    }
+
+    #[test]
+    fn detects_binary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.bin");
+        std::fs::write(&file, b"\x7fELF\x00\x01").unwrap();
+        let content = load(&file, 0, 10);
+        assert_eq!(content, PreviewContent::Binary);
+    }
+
+    #[test]
+    fn lists_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("b.txt"), "").unwrap();
+        let content = load(dir.path(), 0, 10);
+        assert_eq!(
+            content,
+            PreviewContent::Directory(vec![String::from("b.txt"), String::from("sub/")])
+        );
+    }
+}
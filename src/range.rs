@@ -0,0 +1,231 @@
+//! Matchers for locations that span several lines of a file, such as diff hunks and links to a
+//! range of lines on a code host.
+
+use crate::matcher::{LineMatch, MatchResult, Matcher, StreamMatcher, Window};
+use crate::os_str;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // +++ b/src/a.rs, or with a timestamp after a tab as `diff -u` prints it.
+    static ref DIFF_HEADER_REGEX: Regex = Regex::new(r"^\+\+\+ (?P<path>[^\t]+)").unwrap();
+    // @@ -10,7 +12,9 @@ fn f(), the count being 1 when left out.
+    static ref DIFF_HUNK_REGEX: Regex =
+        Regex::new(r"^@@ -\d+(?:,\d+)? \+(?P<line>\d+)(?:,(?P<count>\d+))? @@").unwrap();
+    // https://github.com/o/r/blob/main/src/a.rs#L10-L25, or on GitLab
+    // https://gitlab.com/g/r/-/blob/main/src/a.rs#L10-25
+    static ref PERMALINK_REGEX: Regex = Regex::new(
+        r"https?://[^/\s]+/\S+?/(?:-/)?blob/[^/\s]+/(?P<path>[^#?\s]+)(?:\?[^#\s]*)?(?:#L(?P<line>\d+)(?:C\d+)?(?:-L?(?P<end_line>\d+))?)?",
+    )
+    .unwrap();
+}
+
+/// Positions every hunk of a unified diff in the file of the `+++` header above it, spanning the
+/// lines the hunk has in the new version. The header may be any number of lines up, so it's kept
+/// until the next one.
+#[derive(Default)]
+pub struct DiffHunkMatcher {
+    path: Option<String>,
+}
+
+impl StreamMatcher for DiffHunkMatcher {
+    fn match_window(&mut self, window: &Window) -> Vec<LineMatch> {
+        let line = window.current();
+        if line.starts_with("diff ") {
+            self.path = None;
+        } else if let Some(captures) = DIFF_HEADER_REGEX.captures(line) {
+            self.path = new_path(captures["path"].trim_end());
+        }
+        let Some(captures) = DIFF_HUNK_REGEX.captures(line) else {
+            return vec![];
+        };
+        let Some(path) = self.path.clone() else {
+            return vec![];
+        };
+        let start: u32 = captures["line"].parse().unwrap_or(0);
+        let count: u32 = captures
+            .name("count")
+            .map_or(1, |m| m.as_str().parse().unwrap_or(1));
+        vec![LineMatch {
+            span: captures.get(0).unwrap().range(),
+            result: MatchResult {
+                path,
+                // A hunk that only removes lines is at the line before them, 0 at the top.
+                line_number: Some(start.max(1)),
+                column: None,
+                end_line: (count > 1).then(|| start + count - 1),
+                git_status: None,
+                resolved_path: None,
+            },
+        }]
+    }
+}
+
+/// The path in a `+++` header, without the `b/` prefix of git. Deleted files have none.
+fn new_path(header: &str) -> Option<String> {
+    let path = match header.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+        Some(quoted) => unquote(quoted),
+        None => header.to_string(),
+    };
+    if path == "/dev/null" {
+        return None;
+    }
+    Some(path.strip_prefix("b/").unwrap_or(&path).to_string())
+}
+
+/// Undoes the C-style escapes git quotes unusual paths with, e.g. `caf\303\251.rs` or `a\"b.rs`.
+fn unquote(quoted: &str) -> String {
+    let quoted = os_str::encode(quoted);
+    let mut bytes = Vec::with_capacity(quoted.len());
+    let mut rest = quoted.as_ref();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        let octal = rest
+            .iter()
+            .take(3)
+            .take_while(|b| (b'0'..=b'7').contains(*b))
+            .count();
+        if octal > 0 {
+            let value = rest[..octal]
+                .iter()
+                .fold(0u32, |value, &digit| value * 8 + u32::from(digit - b'0'));
+            bytes.push(value as u8);
+            rest = &rest[octal..];
+            continue;
+        }
+        let Some((&escaped, tail)) = rest.split_first() else {
+            bytes.push(b);
+            break;
+        };
+        rest = tail;
+        bytes.push(match escaped {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'v' => 0x0b,
+            b'f' => 0x0c,
+            b'r' => b'\r',
+            other => other,
+        });
+    }
+    os_str::decode(&bytes).into_owned()
+}
+
+/// Takes the path relative to the repository out of links to a file on GitHub or GitLab, along with
+/// the lines they point to.
+pub struct PermalinkMatcher;
+
+impl Matcher for PermalinkMatcher {
+    fn match_all(&self, line: &str) -> Vec<LineMatch> {
+        PERMALINK_REGEX
+            .captures_iter(line)
+            .filter_map(|captures| {
+                let path = captures.name("path")?;
+                let number = |name: &str| captures.name(name).and_then(|m| m.as_str().parse().ok());
+                let line_number = number("line");
+                Some(LineMatch {
+                    span: path.range(),
+                    result: MatchResult {
+                        path: path.as_str().to_string(),
+                        line_number,
+                        column: None,
+                        end_line: number("end_line")
+                            .filter(|&end| line_number.is_some_and(|line| line < end)),
+                        git_status: None,
+                        resolved_path: None,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matcher::{MatchResult, Matcher, StreamMatcher, Window};
+    use crate::range::{DiffHunkMatcher, PermalinkMatcher};
+
+    fn range(path: &str, line_number: u32, end_line: Option<u32>) -> Option<MatchResult> {
+        Some(MatchResult {
+            path: String::from(path),
+            line_number: Some(line_number),
+            end_line,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn positions_hunks_in_the_file_of_their_header() {
+        let mut matcher = DiffHunkMatcher::default();
+        let lines = [
+            "diff --git a/src/a.rs b/src/a.rs",
+            "--- a/src/a.rs",
+            "+++ b/src/a.rs",
+            "@@ -10,7 +12,9 @@ fn main() {",
+            "+    let x = 1;",
+            "@@ -40 +42 @@",
+            "diff --git a/old.rs b/old.rs",
+            "@@ -1,3 +0,0 @@",
+            "--- a/src/gone.rs",
+            "+++ /dev/null",
+            "@@ -1,3 +0,0 @@",
+            "+++ docs/a b.md\t2024-01-01 10:00:00",
+            "@@ -3,2 +3,0 @@",
+            "diff --git \"a/caf\\303\\251 \\\"x\\\".rs\" \"b/caf\\303\\251 \\\"x\\\".rs\"",
+            "+++ \"b/caf\\303\\251 \\\"x\\\".rs\"",
+            "@@ -1 +1 @@",
+        ];
+        let found: Vec<Option<MatchResult>> = (0..lines.len())
+            .map(|i| {
+                let found = matcher.match_window(&Window::new(&lines, i));
+                found.into_iter().next().map(|m| m.result)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                None,
+                None,
+                None,
+                range("src/a.rs", 12, Some(20)),
+                None,
+                range("src/a.rs", 42, None),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                range("docs/a b.md", 3, None),
+                None,
+                None,
+                range("café \"x\".rs", 1, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_permalinks() {
+        assert_eq!(
+            PermalinkMatcher
+                .match_line("see https://github.com/o/r/blob/0a1b2c/src/a.rs#L10-L25 for details"),
+            range("src/a.rs", 10, Some(25))
+        );
+        assert_eq!(
+            PermalinkMatcher
+                .match_line("https://gitlab.com/g/sub/r/-/blob/main/lib/b.py?plain=1#L7"),
+            range("lib/b.py", 7, None)
+        );
+        assert_eq!(
+            PermalinkMatcher
+                .match_line("https://github.com/o/r/blob/main/README.md")
+                .map(|r| r.path),
+            Some(String::from("README.md"))
+        );
+    }
+}
@@ -0,0 +1,148 @@
+//! Finds the files that paths in the input name when they aren't relative to the cwd, such as paths
+//! relative to the repository root in `git diff` run from a subdirectory, or absolute paths of the
+//! CI machine that built the project.
+
+use crate::os_str;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::env;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    // Where CI services check out the project.
+    static ref BUILD_PREFIXES: Vec<Regex> = vec![
+        // GitHub Actions. /home/runner/work/repo/repo/
+        Regex::new(r"^/home/runner/work/[^/]+/[^/]+/").unwrap(),
+        // GitHub Actions in a container. /github/workspace/
+        Regex::new(r"^/github/workspace/").unwrap(),
+        // GitLab CI. /builds/group/project/, or /builds/xyz/ with a custom clone path.
+        Regex::new(r"^/builds/[^/]+/[^/]+/").unwrap(),
+        Regex::new(r"^/builds/[^/]+/").unwrap(),
+        // Jenkins. /var/lib/jenkins/workspace/job/
+        Regex::new(r"^/var/lib/jenkins/workspace/[^/]+/").unwrap(),
+    ];
+}
+
+pub struct Resolver {
+    /// Where relative paths are looked up, in order. The empty path is the cwd.
+    roots: Vec<PathBuf>,
+    /// Prefixes stripped from absolute paths to make them relative to a root.
+    strip_prefixes: Vec<Regex>,
+}
+
+impl Resolver {
+    /// Looks paths up in the cwd, the root of the enclosing git worktree and then `roots`.
+    /// `strip_prefixes` are tried before the built-in build directories of CI services.
+    pub fn new(roots: Vec<PathBuf>, strip_prefixes: Vec<Regex>) -> Self {
+        let git_root = env::current_dir().ok().and_then(|cwd| git_root(&cwd));
+        let roots = [PathBuf::new()]
+            .into_iter()
+            .chain(git_root)
+            .chain(roots)
+            .collect();
+        Self::with_roots(roots, strip_prefixes)
+    }
+
+    /// Looks paths up in `roots` only.
+    pub fn with_roots(roots: Vec<PathBuf>, strip_prefixes: Vec<Regex>) -> Self {
+        Self {
+            roots,
+            strip_prefixes,
+        }
+    }
+
+    /// Where `path`, which doesn't exist as it is, is found under one of the roots, once stripped of
+    /// a build prefix if it's absolute.
+    pub fn resolve(&self, path: &str) -> Option<String> {
+        let relative: Vec<&str> = if Path::new(path).is_relative() {
+            vec![path]
+        } else {
+            self.strip_prefixes
+                .iter()
+                .chain(BUILD_PREFIXES.iter())
+                .filter_map(|prefix| Some(&path[prefix.find(path)?.end()..]))
+                .collect()
+        };
+        relative
+            .iter()
+            .flat_map(|relative| {
+                let relative = os_str::to_os_string(relative);
+                self.roots.iter().map(move |root| root.join(&relative))
+            })
+            .find(|candidate| candidate.exists())
+            .map(|found| os_str::decode_os_str(found.as_os_str()).into_owned())
+    }
+}
+
+/// The root of the git worktree `dir` is in, if any.
+fn git_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resolve::{Resolver, git_root};
+    use regex::Regex;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    /// A temporary tree with `repo/src/a.rs` and `vendor/v.c`, with `repo` being a git worktree.
+    fn tree() -> TempDir {
+        let tree = tempfile::tempdir().unwrap();
+        let dir = tree.path();
+        fs::create_dir_all(dir.join("repo/.git")).unwrap();
+        fs::create_dir_all(dir.join("repo/src/sub")).unwrap();
+        fs::create_dir_all(dir.join("vendor")).unwrap();
+        fs::write(dir.join("repo/src/a.rs"), "").unwrap();
+        fs::write(dir.join("vendor/v.c"), "").unwrap();
+        tree
+    }
+
+    fn found(path: PathBuf) -> Option<String> {
+        Some(path.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn resolves_against_roots() {
+        let tree = tree();
+        let dir = tree.path();
+        let repo = git_root(&dir.join("repo/src/sub"));
+        let resolver = Resolver::with_roots(vec![dir.join("repo"), dir.join("vendor")], vec![]);
+        let resolved = ["src/a.rs", "v.c", "src/b.rs"].map(|path| resolver.resolve(path));
+
+        assert_eq!(repo, Some(dir.join("repo")));
+        assert_eq!(
+            resolved,
+            [
+                found(dir.join("repo").join("src/a.rs")),
+                found(dir.join("vendor").join("v.c")),
+                None,
+            ]
+        );
+    }
+
+    // The build prefixes are absolute Unix paths, which Windows takes as relative to the drive.
+    #[cfg(unix)]
+    #[test]
+    fn resolves_without_build_prefixes() {
+        let tree = tree();
+        let dir = tree.path();
+        let resolver = Resolver::with_roots(
+            vec![dir.join("repo")],
+            vec![Regex::new(r"^/opt/ci/[^/]+/").unwrap()],
+        );
+        let resolved = [
+            "/home/runner/work/repo/repo/src/a.rs",
+            "/builds/group/repo/src/a.rs",
+            "/opt/ci/job/src/a.rs",
+            "/elsewhere/src/a.rs",
+        ]
+        .map(|path| resolver.resolve(path));
+
+        let a = found(dir.join("repo/src/a.rs"));
+        assert_eq!(resolved, [a.clone(), a.clone(), a, None]);
+    }
+}
@@ -0,0 +1,260 @@
+//! Matchers for stack trace frames, so that pasting a traceback yields one candidate per frame,
+//! positioned at its line.
+
+use crate::matcher::{LineMatch, MatchResult, Matcher};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    // File "app/x.py", line 12, in f
+    static ref PYTHON_FRAME_REGEX: Regex =
+        Regex::new(r#"^\s*File "(?P<path>[^"]+)", line (?P<line>\d+)"#).unwrap();
+    // at com.foo.Bar$Inner.run(Bar.java:42), optionally with a module such as `app//`
+    static ref JAVA_FRAME_REGEX: Regex = Regex::new(
+        r"^\s*at\s+(?:\S+/)?(?P<method>[\w$.<>]+)\((?P<file>[\w$-]+\.(?:java|kt|scala|groovy)):(?P<line>\d+)\)",
+    )
+    .unwrap();
+    // \t/home/me/app/x.go:42 +0x1d
+    static ref GO_FRAME_REGEX: Regex =
+        Regex::new(r"^\s+(?P<path>\S+\.go):(?P<line>\d+)(?: \+0x[0-9a-f]+)?$").unwrap();
+    // at fn (/app/x.js:10:3), at /app/x.js:10:3 or at async file:///app/x.mjs:10:3
+    static ref NODE_FRAME_REGEX: Regex = Regex::new(
+        r"^\s*at (?:async )?(?:.+? \()?(?:file://)?(?P<path>[^()\s]+):(?P<line>\d+):(?P<column>\d+)\)?$",
+    )
+    .unwrap();
+    // thread 'main' panicked at src/main.rs:10:5:, or before Rust 1.73,
+    // thread 'main' panicked at 'oops', src/main.rs:10:5
+    static ref RUST_PANIC_REGEX: Regex = Regex::new(
+        r"panicked at (?:'.*', )?(?P<path>[^\s']+?):(?P<line>\d+):(?P<column>\d+)",
+    )
+    .unwrap();
+}
+
+/// Where Java sources usually live, relative to the project root.
+const JAVA_SOURCE_ROOTS: &[&str] = &[
+    "src/main/java",
+    "src/test/java",
+    "src/main/kotlin",
+    "src/test/kotlin",
+    "src/main/scala",
+    "src",
+    ".",
+];
+
+pub struct PythonTraceMatcher;
+
+impl Matcher for PythonTraceMatcher {
+    fn match_all(&self, line: &str) -> Vec<LineMatch> {
+        match_frame(&PYTHON_FRAME_REGEX, line).into_iter().collect()
+    }
+}
+
+pub struct GoTraceMatcher;
+
+impl Matcher for GoTraceMatcher {
+    fn match_all(&self, line: &str) -> Vec<LineMatch> {
+        match_frame(&GO_FRAME_REGEX, line).into_iter().collect()
+    }
+}
+
+pub struct NodeTraceMatcher;
+
+impl Matcher for NodeTraceMatcher {
+    fn match_all(&self, line: &str) -> Vec<LineMatch> {
+        // Node's own modules, e.g. node:internal/modules/cjs/loader:1105:14, aren't files.
+        match_frame(&NODE_FRAME_REGEX, line)
+            .filter(|m| !m.result.path.starts_with("node:"))
+            .into_iter()
+            .collect()
+    }
+}
+
+pub struct RustPanicMatcher;
+
+impl Matcher for RustPanicMatcher {
+    fn match_all(&self, line: &str) -> Vec<LineMatch> {
+        match_frame(&RUST_PANIC_REGEX, line).into_iter().collect()
+    }
+}
+
+/// Java frames only name the class and the file, so the path is rebuilt from the package and
+/// looked up under the source roots.
+pub struct JavaTraceMatcher {
+    source_roots: Vec<PathBuf>,
+}
+
+impl Default for JavaTraceMatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JavaTraceMatcher {
+    /// Uses the usual Maven and Gradle layouts, in the cwd and in every module directly below it.
+    pub fn new() -> Self {
+        let mut modules = vec![PathBuf::new()];
+        if let Ok(entries) = fs::read_dir(".") {
+            let mut dirs: Vec<PathBuf> = entries
+                .filter_map(|res| res.ok())
+                .map(|e| e.path())
+                .filter(|p| p.join("src").is_dir())
+                .map(|p| p.strip_prefix(".").map(Path::to_path_buf).unwrap_or(p))
+                .collect();
+            dirs.sort();
+            modules.extend(dirs);
+        }
+        let source_roots = modules
+            .iter()
+            .flat_map(|module| JAVA_SOURCE_ROOTS.iter().map(move |root| module.join(root)))
+            .filter(|root| root.is_dir())
+            .collect();
+        Self::with_source_roots(source_roots)
+    }
+
+    pub fn with_source_roots(source_roots: Vec<PathBuf>) -> Self {
+        Self { source_roots }
+    }
+}
+
+impl Matcher for JavaTraceMatcher {
+    fn match_all(&self, line: &str) -> Vec<LineMatch> {
+        let Some(captures) = JAVA_FRAME_REGEX.captures(line) else {
+            return vec![];
+        };
+        // com.foo.Bar$Inner.run -> com/foo, the class and method being the last two segments.
+        let segments: Vec<&str> = captures["method"].split('.').collect();
+        let package = &segments[..segments.len().saturating_sub(2)];
+        let relative: PathBuf = package
+            .iter()
+            .chain(std::iter::once(&&captures["file"]))
+            .collect();
+        let path = self
+            .source_roots
+            .iter()
+            .map(|root| root.join(&relative))
+            .find(|p| p.is_file())
+            .unwrap_or(relative);
+        vec![LineMatch {
+            span: captures.name("file").unwrap().range(),
+            result: MatchResult {
+                path: path.to_string_lossy().trim_start_matches("./").to_string(),
+                line_number: captures["line"].parse().ok(),
+                column: None,
+                end_line: None,
+                git_status: None,
+                resolved_path: None,
+            },
+        }]
+    }
+}
+
+fn match_frame(regex: &Regex, line: &str) -> Option<LineMatch> {
+    let captures = regex.captures(line)?;
+    let path = captures.name("path")?;
+    let number = |name: &str| captures.name(name).and_then(|m| m.as_str().parse().ok());
+    Some(LineMatch {
+        span: path.range(),
+        result: MatchResult {
+            path: path.as_str().to_string(),
+            line_number: number("line"),
+            column: number("column"),
+            end_line: None,
+            git_status: None,
+            resolved_path: None,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matcher::{MatchResult, Matcher};
+    use crate::stack_trace::{
+        GoTraceMatcher, JavaTraceMatcher, NodeTraceMatcher, PythonTraceMatcher, RustPanicMatcher,
+    };
+    use std::path::{Path, PathBuf};
+
+    fn frame(path: &str, line_number: u32, column: Option<u32>) -> Option<MatchResult> {
+        Some(MatchResult {
+            path: String::from(path),
+            line_number: Some(line_number),
+            column,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn matches_python_frames() {
+        assert_eq!(
+            PythonTraceMatcher.match_line(r#"  File "/app/my lib/x.py", line 12, in load"#),
+            frame("/app/my lib/x.py", 12, None)
+        );
+        assert_eq!(
+            PythonTraceMatcher.match_line("Traceback (most recent call last):"),
+            None
+        );
+    }
+
+    #[test]
+    fn matches_java_frames_against_source_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("src/main/java");
+        std::fs::create_dir_all(root.join("com/foo")).unwrap();
+        std::fs::write(root.join("com/foo/Bar.java"), "").unwrap();
+        let matcher =
+            JavaTraceMatcher::with_source_roots(vec![PathBuf::from("nope"), root.clone()]);
+        let found = matcher.match_line("\tat com.foo.Bar$Inner.run(Bar.java:42)");
+        let missing = matcher.match_line("\tat app//org.acme.Main.main(Main.kt:7)");
+        // Compared as paths, as they're joined with the platform's separator.
+        let position = |found: Option<MatchResult>| {
+            found.map(|result| (PathBuf::from(result.path), result.line_number))
+        };
+        assert_eq!(
+            position(found),
+            Some((root.join("com").join("foo").join("Bar.java"), Some(42)))
+        );
+        assert_eq!(
+            position(missing),
+            Some((Path::new("org").join("acme").join("Main.kt"), Some(7)))
+        );
+    }
+
+    #[test]
+    fn matches_go_frames() {
+        assert_eq!(
+            GoTraceMatcher.match_line("\t/home/me/app/server.go:42 +0x1d"),
+            frame("/home/me/app/server.go", 42, None)
+        );
+        assert_eq!(GoTraceMatcher.match_line("main.main()"), None);
+    }
+
+    #[test]
+    fn matches_node_frames() {
+        assert_eq!(
+            NodeTraceMatcher.match_line("    at Object.load (/app/src/x.js:10:3)"),
+            frame("/app/src/x.js", 10, Some(3))
+        );
+        assert_eq!(
+            NodeTraceMatcher.match_line("    at async file:///app/src/y.mjs:4:15"),
+            frame("/app/src/y.mjs", 4, Some(15))
+        );
+        assert_eq!(
+            NodeTraceMatcher
+                .match_line("    at Module._compile (node:internal/modules/cjs/loader:1105:14)"),
+            None
+        );
+    }
+
+    #[test]
+    fn matches_rust_panics() {
+        assert_eq!(
+            RustPanicMatcher.match_line("thread 'main' panicked at src/main.rs:10:5:"),
+            frame("src/main.rs", 10, Some(5))
+        );
+        assert_eq!(
+            RustPanicMatcher.match_line("thread 'main' panicked at 'oops', src/lib.rs:3:9"),
+            frame("src/lib.rs", 3, Some(9))
+        );
+    }
+}
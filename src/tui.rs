@@ -1,17 +1,35 @@
-use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crate::fuzzy::{FuzzyMatch, fuzzy_match};
+use crate::keymap::{Action, Keymap};
+use crate::matcher::MatchResult;
+use crate::os_str;
+use crate::pipe::{Candidate, Found, Source};
+use crate::preview::{self, PreviewContent};
+use crossterm::cursor::Show;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
 use ratatui::{
-    DefaultTerminal, Frame,
+    Frame, Terminal,
+    backend::{Backend, CrosstermBackend},
     layout::{Flex, Layout, Rect},
     prelude::Constraint,
-    style::{Color, Style, Stylize},
-    text::Line,
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
     widgets::{Block, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
-use std::collections::BTreeSet;
-use std::io::{self};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::time::Duration;
 
 #[derive(PartialEq)]
 pub enum TUILoopEvent {
+    /// Run the given shell command on the selection instead of opening the editor.
+    Command(String),
     Continue,
     EarlyReturn,
     Interrupted,
@@ -20,76 +38,560 @@ pub enum TUILoopEvent {
 }
 
 struct TUIState {
+    /// The command being typed at the `:` prompt, if it's open.
+    command_prompt: Option<String>,
     cursor: ListState,
+    filter: Filter,
+    is_reading: bool,
     is_showing_help: bool,
-    items: Vec<String>,
+    /// Whether the input lines are shown under each candidate.
+    is_showing_sources: bool,
+    per_line: bool,
+    items: Vec<Item>,
+    /// The group of each candidate, in the order they came in, and its place in the group.
+    item_slots: Vec<(usize, usize)>,
+    /// In per-line mode, the group of each path, which lists its lines together. Otherwise every
+    /// candidate is a group of its own.
+    groups: HashMap<String, usize>,
+    group_sizes: GroupSizes,
+    keymap: Keymap,
     main_area_height: u16,
-    selected: BTreeSet<usize>,
-}
-
-const HELP_MESSAGE_ENTRIES: &[(&str, &str)] = &[
-    ("q", "Exit"),
-    ("?", "Toggle help"),
-    ("", ""),
-    ("k/↑", "Move up"),
-    ("j/↓", "Move down"),
-    ("u", "Half page up"),
-    ("d", "Half page down"),
-    ("b/PgUp", "Full page up"),
-    ("f/PgDn", "Full page down"),
-    ("g/Home", "Go to top"),
-    ("G/End", "Go to bottom"),
-    ("", ""),
-    ("space", "Toggle selection"),
-    ("enter", "Open selected files"),
-    ("a", "Select all/none"),
-    ("h/←", "Previous selected"),
-    ("l/→", "Next selected"),
+    preview: Preview,
+    /// Whether items are selected as they come in.
+    select_incoming: bool,
+    spinner_frame: usize,
+    theme: Theme,
+}
+
+/// A candidate in the list.
+struct Item {
+    /// The position of the candidate in the order they came in.
+    id: usize,
+    is_selected: bool,
+    result: MatchResult,
+    /// The input lines the candidate was found on, up to `MAX_SOURCES`.
+    sources: Vec<Source>,
+    /// How many more lines it was found on.
+    more_sources: usize,
+}
+
+/// Fuzzy filter state. The query sticks around after the prompt is dismissed so that matches stay
+/// highlighted and `n`/`N` can jump between them.
+#[derive(Default)]
+struct Filter {
+    is_prompting: bool,
+    query: String,
+    /// Every matching item, keyed by its index in `TUIState.items`.
+    matches: BTreeMap<usize, FuzzyMatch>,
+    /// Ids of the matching items, best match first. This is what's listed while prompting.
+    ranking: Vec<usize>,
+    /// Cursor into `ranking`.
+    cursor: ListState,
+}
+
+/// Preview pane state. The scroll offset is reset whenever the cursor moves to another item.
+#[derive(Default)]
+struct Preview {
+    is_showing: bool,
+    /// The item the scroll offset belongs to.
+    item: Option<usize>,
+    /// 0-based first visible line, or None to show the matched line with some context above it.
+    scroll: Option<usize>,
+    height: u16,
+    /// The last loaded content and the (item, first line, height) it was loaded for, so that the
+    /// file isn't re-read on every frame.
+    cache: Option<((usize, usize, u16), PreviewContent)>,
+}
+
+impl Filter {
+    fn move_cursor_up(&mut self) {
+        if self.cursor.selected() == Some(0) {
+            self.cursor.select(self.ranking.len().checked_sub(1));
+        } else {
+            self.cursor.select_previous();
+        }
+    }
+
+    fn move_cursor_down(&mut self) {
+        if self
+            .cursor
+            .selected()
+            .is_some_and(|i| i + 1 >= self.ranking.len())
+        {
+            self.cursor.select_first();
+        } else {
+            self.cursor.select_next();
+        }
+    }
+}
+
+/// The number of items in each group, in the order they're listed, along with a Fenwick tree of
+/// them to count the items before any group without going through the ones before it.
+#[derive(Default)]
+struct GroupSizes {
+    sizes: Vec<usize>,
+    /// Element `i` holds the sizes of the groups from `i + 1 - lowest_bit(i + 1)` to `i` summed.
+    tree: Vec<usize>,
+}
+
+impl GroupSizes {
+    /// Adds an empty group after the others and returns its index.
+    fn push(&mut self) -> usize {
+        let group = self.sizes.len();
+        let lowest_bit = (group + 1) & (group + 1).wrapping_neg();
+        let sum = self.count_before(group) - self.count_before(group + 1 - lowest_bit);
+        self.sizes.push(0);
+        self.tree.push(sum);
+        group
+    }
+
+    fn grow(&mut self, group: usize) {
+        self.sizes[group] += 1;
+        let mut i = group + 1;
+        while i <= self.tree.len() {
+            self.tree[i - 1] += 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    fn len(&self, group: usize) -> usize {
+        self.sizes[group]
+    }
+
+    /// How many items the groups before `group` have.
+    fn count_before(&self, group: usize) -> usize {
+        let mut count = 0;
+        let mut i = group;
+        while i > 0 {
+            count += self.tree[i - 1];
+            i -= i & i.wrapping_neg();
+        }
+        count
+    }
+}
+
+/// How long to wait for a key press before checking the input stream again. This also drives the
+/// spinner animation.
+const TICK_RATE: Duration = Duration::from_millis(80);
+
+/// How long to wait for the first candidate before showing the TUI, so that inputs without any
+/// paths in them exit right away instead of flashing an empty list.
+const FIRST_CANDIDATE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Input lines kept per candidate. Past these, the list only says how many more there are.
+const MAX_SOURCES: usize = 5;
+
+/// Indentation of the input lines under their candidate, lining them up with the path.
+const SOURCE_INDENT: &str = "    ";
+
+/// Characters kept before the path when an input line is too long to show in full.
+const SOURCE_CONTEXT: usize = 12;
+
+const SPINNER_FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// Rows of the help popup: the actions whose keys are listed, and what they do. Rows without
+/// actions are spacers.
+const HELP_MESSAGE_ENTRIES: &[(&[Action], &str)] = &[
+    (&[Action::Quit], "Exit"),
+    (&[Action::ToggleHelp], "Toggle help"),
+    (&[], ""),
+    (&[Action::Up], "Move up"),
+    (&[Action::Down], "Move down"),
+    (&[Action::HalfPageUp], "Half page up"),
+    (&[Action::HalfPageDown], "Half page down"),
+    (&[Action::PageUp], "Full page up"),
+    (&[Action::PageDown], "Full page down"),
+    (&[Action::Top], "Go to top"),
+    (&[Action::Bottom], "Go to bottom"),
+    (&[], ""),
+    (&[Action::ToggleSelection], "Toggle selection"),
+    (&[Action::Submit], "Open selected files"),
+    (&[Action::SelectAll], "Select all/none"),
+    (&[Action::PreviousSelected], "Previous selected"),
+    (&[Action::NextSelected], "Next selected"),
+    (&[], ""),
+    (&[Action::Filter], "Fuzzy filter"),
+    (
+        &[Action::NextMatch, Action::PreviousMatch],
+        "Next/previous match",
+    ),
+    (&[], ""),
+    (&[Action::ToggleSources], "Toggle input lines"),
+    (&[Action::TogglePreview], "Toggle preview"),
+    (
+        &[Action::ScrollPreviewDown, Action::ScrollPreviewUp],
+        "Scroll preview",
+    ),
+    (&[Action::Command], "Run command on selected"),
 ];
 
+/// Colors used by the TUI.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    /// Background of the row under the cursor. Without one, the row's colors are reversed.
+    pub cursor: Option<Color>,
+    /// Foreground of selected rows.
+    pub selected: Option<Color>,
+    /// Characters matching the fuzzy filter.
+    pub filter_match: Color,
+    /// Background of the matched line in the preview pane.
+    pub preview_line: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            cursor: None,
+            selected: None,
+            filter_match: Color::Yellow,
+            preview_line: Color::DarkGray,
+        }
+    }
+}
+
+impl Theme {
+    /// Sets the color called `name` in the config file from a color name, `#rrggbb` or an ANSI
+    /// index.
+    pub fn set(&mut self, name: &str, color: &str) -> Result<(), String> {
+        let color: Color = color
+            .parse()
+            .map_err(|_| format!("Unknown color `{color}`"))?;
+        match name {
+            "cursor" => self.cursor = Some(color),
+            "selected" => self.selected = Some(color),
+            "match" => self.filter_match = color,
+            "preview_line" => self.preview_line = color,
+            _ => return Err(format!("Unknown color setting `{name}`")),
+        }
+        Ok(())
+    }
+}
+
+/// How the picker looks and behaves, beyond the candidates themselves.
+#[derive(Clone, Debug, Default)]
+pub struct PickerOptions {
+    pub keymap: Keymap,
+    /// Candidates are lines rather than paths, so they're grouped under their path and shown with
+    /// their input line.
+    pub per_line: bool,
+    /// Select every candidate as it comes in.
+    pub select_all: bool,
+    pub theme: Theme,
+}
+
 impl TUIState {
-    fn new(items: Vec<String>) -> TUIState {
-        assert!(!items.is_empty());
-        let mut s = ListState::default();
-        s.select(Some(0));
+    fn new(options: PickerOptions) -> TUIState {
         TUIState {
-            items,
-            cursor: s,
-            selected: BTreeSet::new(),
+            items: vec![],
+            command_prompt: None,
+            cursor: ListState::default(),
+            filter: Filter::default(),
             main_area_height: 0,
+            preview: Preview::default(),
+            is_reading: true,
             is_showing_help: false,
+            is_showing_sources: options.per_line,
+            per_line: options.per_line,
+            item_slots: vec![],
+            groups: HashMap::new(),
+            group_sizes: GroupSizes::default(),
+            keymap: options.keymap,
+            select_incoming: options.select_all,
+            spinner_frame: 0,
+            theme: options.theme,
+        }
+    }
+
+    fn receive(&mut self, found: Found) {
+        match found {
+            Found::Candidate(Candidate { result, source }) => {
+                let group = match self.groups.get(&result.path) {
+                    Some(&group) => group,
+                    None => {
+                        let group = self.group_sizes.push();
+                        if self.per_line {
+                            self.groups.insert(result.path.clone(), group);
+                        }
+                        group
+                    }
+                };
+                let offset = self.group_sizes.len(group);
+                let index = self.group_sizes.count_before(group) + offset;
+                self.group_sizes.grow(group);
+                if index < self.items.len() {
+                    self.shift_items_from(index);
+                }
+                self.items.insert(
+                    index,
+                    Item {
+                        id: self.item_slots.len(),
+                        is_selected: self.select_incoming,
+                        result,
+                        sources: source.into_iter().collect(),
+                        more_sources: 0,
+                    },
+                );
+                self.item_slots.push((group, offset));
+                if self.cursor.selected().is_none() {
+                    self.cursor.select(Some(0));
+                }
+            }
+            Found::Positioned(i, position) => {
+                if let Some(item) = self.item_mut(i) {
+                    item.result.take_position(&position);
+                }
+            }
+            Found::Again(i, source) => {
+                let Some(item) = self.item_mut(i) else {
+                    return;
+                };
+                // A path can be found twice on the same line, e.g. in `diff a/x b/x`.
+                if item.sources.last().is_some_and(|s| s.text == source.text) {
+                    return;
+                }
+                if item.sources.len() < MAX_SOURCES {
+                    item.sources.push(source);
+                } else {
+                    item.more_sources += 1;
+                }
+            }
+        }
+    }
+
+    /// Where the item with `id` sits in `items`.
+    fn position(&self, id: usize) -> Option<usize> {
+        let &(group, offset) = self.item_slots.get(id)?;
+        Some(self.group_sizes.count_before(group) + offset)
+    }
+
+    fn item_mut(&mut self, id: usize) -> Option<&mut Item> {
+        let position = self.position(id)?;
+        self.items.get_mut(position)
+    }
+
+    /// Makes room for an item at `index` by moving the cursor and the preview down by one if
+    /// they're past it. Everything else refers to items by id.
+    fn shift_items_from(&mut self, index: usize) {
+        let shift = |i: &mut usize| {
+            if *i >= index {
+                *i += 1;
+            }
+        };
+        if let Some(cursor) = self.cursor.selected().filter(|&i| i >= index) {
+            self.cursor.select(Some(cursor + 1));
         }
+        if let Some(item) = self.preview.item.as_mut() {
+            shift(item);
+        }
+        if let Some(((item, ..), _)) = self.preview.cache.as_mut() {
+            shift(item);
+        }
+    }
+
+    /// Moves every candidate that has arrived so far into the list without blocking.
+    fn drain_candidates(&mut self, candidates: &Receiver<io::Result<Found>>) -> io::Result<()> {
+        let count = self.items.len();
+        loop {
+            match candidates.try_recv() {
+                Ok(found) => self.receive(found?),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.is_reading = false;
+                    break;
+                }
+            }
+        }
+        if self.items.len() != count {
+            self.refresh_filter();
+        }
+        Ok(())
+    }
+
+    /// Re-runs the fuzzy filter against all items, keeping the filter cursor on the same item
+    /// where possible.
+    fn refresh_filter(&mut self) {
+        let filter = &mut self.filter;
+        if !filter.is_prompting && filter.query.is_empty() {
+            filter.matches.clear();
+            filter.ranking.clear();
+            return;
+        }
+        let current = filter
+            .cursor
+            .selected()
+            .and_then(|i| filter.ranking.get(i).copied());
+
+        filter.matches = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| fuzzy_match(&filter.query, &item.result.path).map(|m| (i, m)))
+            .collect();
+        let mut ranking: Vec<usize> = filter.matches.keys().copied().collect();
+        // Stable sort, so equally scored items keep their input order.
+        ranking.sort_by_key(|i| std::cmp::Reverse(filter.matches[i].score));
+        filter.ranking = ranking.into_iter().map(|i| self.items[i].id).collect();
+
+        let position = current
+            .and_then(|c| filter.ranking.iter().position(|&i| i == c))
+            .unwrap_or(0);
+        filter
+            .cursor
+            .select((!filter.ranking.is_empty()).then_some(position));
+    }
+
+    /// The item under the cursor, in whichever list is currently shown.
+    fn current_item(&self) -> Option<usize> {
+        let item = if self.filter.is_prompting {
+            self.filter
+                .cursor
+                .selected()
+                .and_then(|i| self.filter.ranking.get(i))
+                .and_then(|&id| self.position(id))
+        } else {
+            self.cursor.selected()
+        };
+        item.filter(|&i| i < self.items.len())
+    }
+
+    /// First line of `item` to show in the preview pane.
+    fn preview_first_line(&self, item: usize) -> usize {
+        self.preview.scroll.unwrap_or_else(|| {
+            let context = self.preview.height as usize / 3;
+            self.items[item]
+                .result
+                .line_number
+                .map_or(0, |l| (l as usize).saturating_sub(1 + context))
+        })
+    }
+
+    fn scroll_preview_down(&mut self) {
+        let Some(item) = self.current_item() else {
+            return;
+        };
+        // Don't scroll past the end of what's there to show.
+        let is_window_full = match &self.preview.cache {
+            Some((_, PreviewContent::Text { lines, .. })) => {
+                lines.len() >= self.preview.height as usize
+            }
+            Some((_, PreviewContent::Directory(entries))) => {
+                entries.len() > self.preview_first_line(item) + self.preview.height as usize
+            }
+            _ => false,
+        };
+        if is_window_full {
+            self.preview.scroll = Some(self.preview_first_line(item) + 1);
+        }
+    }
+
+    fn scroll_preview_up(&mut self) {
+        if let Some(item) = self.current_item() {
+            self.preview.scroll = Some(self.preview_first_line(item).saturating_sub(1));
+        }
+    }
+
+    fn toggle_selection(&mut self, idx: usize) {
+        if let Some(item) = self.items.get_mut(idx) {
+            item.is_selected = !item.is_selected;
+        }
+    }
+
+    /// Indices of the selected items, in the order they're listed.
+    fn selected(&self) -> Vec<usize> {
+        (0..self.items.len())
+            .filter(|&i| self.items[i].is_selected)
+            .collect()
     }
 }
 
-pub fn run(candidates: Vec<String>) -> io::Result<(Vec<String>, TUILoopEvent)> {
-    if candidates.is_empty() {
-        return Ok((vec![], TUILoopEvent::EarlyReturn));
+/// Interactive selection over a stream of candidates. It can be run more than once, e.g. to get
+/// back to the list after the editor exits, and keeps everything received so far in between.
+pub struct Picker {
+    candidates: Receiver<io::Result<Found>>,
+    state: TUIState,
+}
+
+impl Picker {
+    pub fn new(candidates: Receiver<io::Result<Found>>, options: PickerOptions) -> Self {
+        Picker {
+            candidates,
+            state: TUIState::new(options),
+        }
+    }
+
+    pub fn run(&mut self) -> io::Result<(Vec<Candidate>, TUILoopEvent)> {
+        let tui_state = &mut self.state;
+        if tui_state.is_reading && tui_state.items.is_empty() {
+            match self.candidates.recv_timeout(FIRST_CANDIDATE_TIMEOUT) {
+                Ok(found) => tui_state.receive(found?),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Ok((vec![], TUILoopEvent::EarlyReturn));
+                }
+            }
+        }
+        if io::stdout().is_terminal() {
+            let terminal = ratatui::init();
+            let result = run_selection(terminal, tui_state, &self.candidates);
+            ratatui::restore();
+            result
+        } else {
+            // stdout is reserved for printing the selection, draw on the controlling terminal
+            // instead.
+            let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+            enable_raw_mode()?;
+            let _restore = TtyRestorer;
+            let mut terminal = Terminal::new(CrosstermBackend::new(tty))?;
+            execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+            run_selection(terminal, tui_state, &self.candidates)
+        }
+    }
+}
+
+/// Puts the controlling terminal back the way it was when dropped, however the picker exits.
+struct TtyRestorer;
+
+impl Drop for TtyRestorer {
+    fn drop(&mut self) {
+        if let Ok(mut tty) = OpenOptions::new().write(true).open("/dev/tty") {
+            let _ = execute!(tty, LeaveAlternateScreen, Show);
+        }
+        let _ = disable_raw_mode();
     }
-    let terminal = ratatui::init();
-    let mut tui_state = TUIState::new(candidates);
-    let result = run_selection(terminal, &mut tui_state);
-    ratatui::restore();
-    result
 }
 
 fn run_selection(
-    mut terminal: DefaultTerminal,
+    mut terminal: Terminal<impl Backend>,
     tui_state: &mut TUIState,
-) -> io::Result<(Vec<String>, TUILoopEvent)> {
+    candidates: &Receiver<io::Result<Found>>,
+) -> io::Result<(Vec<Candidate>, TUILoopEvent)> {
     loop {
+        if tui_state.is_reading {
+            tui_state.drain_candidates(candidates)?;
+            tui_state.spinner_frame = (tui_state.spinner_frame + 1) % SPINNER_FRAMES.len();
+            if !tui_state.is_reading && tui_state.items.is_empty() {
+                break Ok((vec![], TUILoopEvent::EarlyReturn));
+            }
+        }
         terminal.draw(|frame| render(frame, tui_state))?;
+        if !event::poll(TICK_RATE)? {
+            continue;
+        }
         match handle_keypress(tui_state)? {
             TUILoopEvent::Continue => {}
             TUILoopEvent::Quit => break Ok((vec![], TUILoopEvent::Quit)),
-            TUILoopEvent::Submit => {
+            event @ (TUILoopEvent::Submit | TUILoopEvent::Command(_)) => {
                 break Ok((
                     tui_state
-                        .selected
+                        .items
                         .iter()
-                        .map(|i| tui_state.items[*i].clone())
+                        .filter(|item| item.is_selected)
+                        .map(|item| Candidate {
+                            result: item.result.clone(),
+                            source: item.sources.first().cloned(),
+                        })
                         .collect(),
-                    TUILoopEvent::Submit,
+                    event,
                 ));
             }
             // I guess this is a way of handling ctrl-c signals :/
@@ -105,21 +607,46 @@ fn handle_keypress(tui_state: &mut TUIState) -> io::Result<TUILoopEvent> {
     let Event::Key(key) = event::read()? else {
         return Ok(TUILoopEvent::Continue);
     };
+    Ok(handle_key(tui_state, key))
+}
+
+fn handle_key(tui_state: &mut TUIState, key: KeyEvent) -> TUILoopEvent {
     if key.kind != KeyEventKind::Press {
-        return Ok(TUILoopEvent::Continue);
+        return TUILoopEvent::Continue;
+    }
+    if tui_state.command_prompt.is_some() {
+        return handle_command_keypress(tui_state, key);
+    }
+    if tui_state.filter.is_prompting {
+        return handle_filter_keypress(tui_state, key);
+    }
+    if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+        return TUILoopEvent::Interrupted;
+    }
+    let Some(action) = tui_state.keymap.action(key) else {
+        return TUILoopEvent::Continue;
+    };
+    // Nothing to move around in until the first candidate shows up.
+    if tui_state.items.is_empty() {
+        return match action {
+            Action::Quit => TUILoopEvent::Quit,
+            Action::ToggleHelp => {
+                tui_state.is_showing_help = !tui_state.is_showing_help;
+                TUILoopEvent::Continue
+            }
+            _ => TUILoopEvent::Continue,
+        };
     }
 
-    match key.code {
-        // down
-        KeyCode::Char('j') | KeyCode::Down => {
+    match action {
+        Action::Down => {
             if tui_state.cursor.selected().unwrap() == tui_state.items.len() - 1 {
                 tui_state.cursor.select_first()
             } else {
                 tui_state.cursor.select_next()
             }
         }
-        // down by 1/2 page
-        KeyCode::Char('d') => {
+        Action::HalfPageDown => {
             if tui_state.cursor.selected().unwrap() == tui_state.items.len() - 1 {
                 tui_state.cursor.select_first()
             } else {
@@ -128,146 +655,571 @@ fn handle_keypress(tui_state: &mut TUIState) -> io::Result<TUILoopEvent> {
                     .scroll_down_by(tui_state.main_area_height / 2);
             }
         }
-        // down by 1 page
-        KeyCode::Char('f') | KeyCode::PageDown => {
+        Action::PageDown => {
             if tui_state.cursor.selected().unwrap() == tui_state.items.len() - 1 {
                 tui_state.cursor.select_first()
             } else {
                 tui_state.cursor.scroll_down_by(tui_state.main_area_height);
             }
         }
-        // up
-        KeyCode::Char('k') | KeyCode::Up => {
+        Action::Up => {
             if tui_state.cursor.selected().unwrap() == 0 {
-                tui_state.cursor.select_last()
+                tui_state.cursor.select(Some(tui_state.items.len() - 1))
             } else {
                 tui_state.cursor.select_previous()
             }
         }
-        // up by 1/2 page
-        KeyCode::Char('u') => {
+        Action::HalfPageUp => {
             if tui_state.cursor.selected().unwrap() == 0 {
-                tui_state.cursor.select_last()
+                tui_state.cursor.select(Some(tui_state.items.len() - 1))
             } else {
                 tui_state
                     .cursor
                     .scroll_up_by(tui_state.main_area_height / 2);
             }
         }
-        // up by 1 page
-        KeyCode::Char('b') | KeyCode::PageUp => {
+        Action::PageUp => {
             if tui_state.cursor.selected().unwrap() == 0 {
-                tui_state.cursor.select_last()
+                tui_state.cursor.select(Some(tui_state.items.len() - 1))
             } else {
                 tui_state.cursor.scroll_up_by(tui_state.main_area_height);
             }
         }
-        KeyCode::Char('h') | KeyCode::Left => {
+        Action::PreviousSelected => {
             let current = tui_state.cursor.selected().unwrap();
-            if tui_state.selected.is_empty() {
-            } else if let Some(&prev) = tui_state.selected.range(..current).next_back() {
+            let selected = tui_state.selected();
+            if let Some(&prev) = selected
+                .iter()
+                .rev()
+                .find(|&&i| i < current)
+                .or(selected.last())
+            {
                 tui_state.cursor.select(Some(prev));
-            } else {
-                tui_state.cursor.select(tui_state.selected.last().copied());
             }
         }
-        KeyCode::Char('l') | KeyCode::Right => {
+        Action::NextSelected => {
             let current = tui_state.cursor.selected().unwrap();
-            if tui_state.selected.is_empty() {
-            } else if let Some(&next) = tui_state.selected.range(current + 1..).next() {
+            let selected = tui_state.selected();
+            if let Some(&next) = selected.iter().find(|&&i| i > current).or(selected.first()) {
                 tui_state.cursor.select(Some(next));
-            } else {
-                tui_state.cursor.select(tui_state.selected.first().copied());
             }
         }
-        // top of the list
-        KeyCode::Char('g') | KeyCode::Home => tui_state.cursor.select_first(),
-        // bottom of the list
-        KeyCode::Char('G') | KeyCode::End => tui_state.cursor.select_last(),
-        KeyCode::Char('?') => tui_state.is_showing_help = !tui_state.is_showing_help,
-        KeyCode::Char(' ') => {
+        Action::Top => tui_state.cursor.select_first(),
+        Action::Bottom => tui_state.cursor.select(Some(tui_state.items.len() - 1)),
+        Action::ToggleHelp => tui_state.is_showing_help = !tui_state.is_showing_help,
+        Action::ToggleSelection => {
             let idx = tui_state
                 .cursor
                 .selected()
                 .expect("There should always be one item selected.");
-            if tui_state.selected.contains(&idx) {
-                tui_state.selected.remove(&idx);
-            } else {
-                tui_state.selected.insert(idx);
+            tui_state.toggle_selection(idx);
+        }
+        Action::ToggleSources => tui_state.is_showing_sources = !tui_state.is_showing_sources,
+        Action::TogglePreview => tui_state.preview.is_showing = !tui_state.preview.is_showing,
+        Action::ScrollPreviewDown if tui_state.preview.is_showing => {
+            tui_state.scroll_preview_down()
+        }
+        Action::ScrollPreviewUp if tui_state.preview.is_showing => tui_state.scroll_preview_up(),
+        Action::Command => tui_state.command_prompt = Some(String::new()),
+        Action::Filter => {
+            tui_state.filter.is_prompting = true;
+            tui_state.filter.query.clear();
+            tui_state.filter.cursor.select(tui_state.cursor.selected());
+            tui_state.refresh_filter();
+        }
+        Action::NextMatch => {
+            let current = tui_state.cursor.selected().unwrap();
+            let matches = &tui_state.filter.matches;
+            if let Some((&next, _)) = matches
+                .range(current + 1..)
+                .next()
+                .or(matches.first_key_value())
+            {
+                tui_state.cursor.select(Some(next));
             }
         }
-        KeyCode::Char('a') => {
-            if tui_state.selected.len() == tui_state.items.len() {
-                tui_state.selected.clear();
-            } else {
-                tui_state.selected.clear();
-                for i in 0..tui_state.items.len() {
-                    tui_state.selected.insert(i);
-                }
+        Action::PreviousMatch => {
+            let current = tui_state.cursor.selected().unwrap();
+            let matches = &tui_state.filter.matches;
+            if let Some((&prev, _)) = matches
+                .range(..current)
+                .next_back()
+                .or(matches.last_key_value())
+            {
+                tui_state.cursor.select(Some(prev));
             }
         }
-        KeyCode::Char('q') => return Ok(TUILoopEvent::Quit),
-        KeyCode::Esc => return Ok(TUILoopEvent::Quit),
-        KeyCode::Enter => return Ok(TUILoopEvent::Submit),
-        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-            return Ok(TUILoopEvent::Interrupted);
+        Action::SelectAll => {
+            let is_selecting = !tui_state.items.iter().all(|item| item.is_selected);
+            for item in &mut tui_state.items {
+                item.is_selected = is_selecting;
+            }
         }
+        Action::Quit => return TUILoopEvent::Quit,
+        Action::Submit => return TUILoopEvent::Submit,
+        _ => return TUILoopEvent::Continue,
+    }
+    TUILoopEvent::Continue
+}
 
-        _ => return Ok(TUILoopEvent::Continue),
+/// While the command prompt is open, printable keys edit the command. Enter runs it on the
+/// selection, Esc closes the prompt.
+fn handle_command_keypress(tui_state: &mut TUIState, key: KeyEvent) -> TUILoopEvent {
+    let Some(command) = tui_state.command_prompt.as_mut() else {
+        return TUILoopEvent::Continue;
+    };
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Char('c') if ctrl => return TUILoopEvent::Interrupted,
+        KeyCode::Esc => tui_state.command_prompt = None,
+        KeyCode::Enter => {
+            let command = command.trim().to_string();
+            tui_state.command_prompt = None;
+            if !command.is_empty() {
+                return TUILoopEvent::Command(command);
+            }
+        }
+        KeyCode::Backspace => {
+            command.pop();
+        }
+        KeyCode::Char(c) if !ctrl => command.push(c),
+        _ => {}
+    }
+    TUILoopEvent::Continue
+}
+
+/// While the filter prompt is open, printable keys edit the query and the list only shows matches.
+/// Enter keeps the query around for highlighting and `n`/`N`, Esc discards it.
+fn handle_filter_keypress(tui_state: &mut TUIState, key: KeyEvent) -> TUILoopEvent {
+    let filter = &mut tui_state.filter;
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let current = filter.cursor.selected();
+    match key.code {
+        KeyCode::Char('c') if ctrl => return TUILoopEvent::Interrupted,
+        KeyCode::Esc => {
+            filter.is_prompting = false;
+            filter.query.clear();
+            tui_state.refresh_filter();
+        }
+        KeyCode::Enter => {
+            filter.is_prompting = false;
+            let chosen = current.and_then(|i| filter.ranking.get(i).copied());
+            if let Some(idx) = chosen.and_then(|id| tui_state.position(id)) {
+                tui_state.cursor.select(Some(idx));
+            }
+            tui_state.refresh_filter();
+        }
+        KeyCode::Up => filter.move_cursor_up(),
+        KeyCode::Char('p' | 'k') if ctrl => filter.move_cursor_up(),
+        KeyCode::Down => filter.move_cursor_down(),
+        KeyCode::Char('n' | 'j') if ctrl => filter.move_cursor_down(),
+        KeyCode::Tab => {
+            let chosen = current.and_then(|i| filter.ranking.get(i).copied());
+            if let Some(idx) = chosen.and_then(|id| tui_state.position(id)) {
+                tui_state.toggle_selection(idx);
+            }
+        }
+        KeyCode::Backspace => {
+            filter.query.pop();
+            tui_state.refresh_filter();
+        }
+        KeyCode::Char(c) if !ctrl => {
+            filter.query.push(c);
+            tui_state.refresh_filter();
+        }
+        _ => {}
     }
-    Ok(TUILoopEvent::Continue)
+    TUILoopEvent::Continue
 }
 
 fn render(frame: &mut Frame, tui_state: &mut TUIState) {
-    let items: Vec<ListItem> = tui_state
-        .items
+    // Paging can overshoot the last item, which the list would only correct while being drawn.
+    if let Some(row) = tui_state.cursor.selected()
+        && row >= tui_state.items.len()
+    {
+        tui_state
+            .cursor
+            .select(tui_state.items.len().checked_sub(1));
+    }
+    let filter = &tui_state.filter;
+    let prompt_height =
+        if tui_state.command_prompt.is_some() || filter.is_prompting || !filter.query.is_empty() {
+            1
+        } else {
+            0
+        };
+    let [main_area, sub_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(prompt_height)])
+            .areas(frame.area());
+    let [main_area, preview_area] = if tui_state.preview.is_showing {
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .areas(main_area)
+    } else {
+        [main_area, Rect::default()]
+    };
+    let list_width = (main_area.width as usize).saturating_sub(2);
+    let source_width = list_width.saturating_sub(SOURCE_INDENT.len());
+    // Lines of the same path are listed under it, unless the filter has reordered them.
+    let is_grouped = tui_state.per_line && !filter.is_prompting;
+
+    let rows: Vec<usize> = if filter.is_prompting {
+        filter
+            .ranking
+            .iter()
+            .filter_map(|&id| tui_state.position(id))
+            .collect()
+    } else {
+        (0..tui_state.items.len()).collect()
+    };
+    let cursor = if filter.is_prompting {
+        filter.cursor.selected()
+    } else {
+        tui_state.cursor.selected()
+    };
+    let items: Vec<ListItem> = rows
         .iter()
         .enumerate()
-        .map(|(i, item)| {
-            let display_text = if tui_state.selected.contains(&i) {
-                format!("[●] {}", item.as_str())
-            } else {
-                format!("[ ] {}", item.as_str())
-            };
-            let style = if tui_state.cursor.selected() == Some(i) {
-                Style::default()
-                    .bg(Color::Reset)
-                    .fg(Color::Reset)
-                    .reversed()
+        .map(|(row, &i)| {
+            let item = &tui_state.items[i];
+            let marker = if item.is_selected { "[●] " } else { "[ ] " };
+            let positions = filter
+                .matches
+                .get(&i)
+                .map_or(&[][..], |m| m.positions.as_slice());
+            let path = highlight(&item.result.path, positions, tui_state.theme.filter_match);
+            let mut lines = vec![];
+            let mut spans = vec![Span::raw(marker)];
+            if is_grouped {
+                let previous = row
+                    .checked_sub(1)
+                    .map(|r| &tui_state.items[rows[r]].result.path);
+                if previous != Some(&item.result.path) {
+                    // Padded to keep the cursor's colors off the heading.
+                    let mut heading = Line::from(path).style(Style::reset().bold());
+                    heading.push_span(" ".repeat(list_width.saturating_sub(heading.width())));
+                    lines.push(heading);
+                }
+                match position(&item.result) {
+                    Some(position) => spans.push(Span::raw(position)),
+                    None => spans.push(Span::raw("(no line)").dim()),
+                }
             } else {
-                Style::default()
-            };
-            ListItem::new(display_text).style(style)
+                if let Some(status) = &item.result.git_status {
+                    let status = Span::raw(format!("{status:<2} "));
+                    spans.push(if item.result.is_deleted() {
+                        status.red()
+                    } else {
+                        status.dim()
+                    });
+                }
+                spans.extend(path);
+                // Otherwise it's only where the path was first found.
+                if tui_state.per_line {
+                    spans.extend(position(&item.result).map(Span::raw));
+                } else {
+                    spans.extend(position(&item.result).map(|p| Span::raw(p).dim()));
+                }
+            }
+            lines.push(Line::from(spans));
+            if tui_state.is_showing_sources {
+                lines.extend(
+                    item.sources
+                        .iter()
+                        .map(|source| source_line(source, source_width)),
+                );
+                if item.more_sources > 0 {
+                    lines.push(
+                        Line::from(format!(
+                            "{SOURCE_INDENT}… {} more line{}",
+                            item.more_sources,
+                            if item.more_sources == 1 { "" } else { "s" }
+                        ))
+                        .dim(),
+                    );
+                }
+            }
+            let theme = &tui_state.theme;
+            let mut style = Style::default();
+            if let Some(color) = theme.selected.filter(|_| item.is_selected) {
+                style = style.fg(color);
+            }
+            if cursor == Some(row) {
+                style = match theme.cursor {
+                    Some(color) => style.bg(color),
+                    None => style.bg(Color::Reset).fg(Color::Reset).reversed(),
+                };
+            }
+            ListItem::new(lines).style(style)
         })
         .collect();
     let list = List::new(items)
         // .highlight_symbol("  ")
         .block(Block::bordered());
-    let [main_area, sub_area] =
-        Layout::vertical([Constraint::Percentage(98), Constraint::Percentage(2)])
-            .areas(frame.area());
-    frame.render_stateful_widget(list, main_area, &mut tui_state.cursor);
-    tui_state.main_area_height = main_area.height;
-    frame.render_widget(
-        Block::bordered()
-            .title_bottom(
-                Line::from(format!(
-                    " {}/{} ",
-                    tui_state.cursor.selected().unwrap() + 1,
-                    tui_state.items.len(),
-                ))
-                .left_aligned(),
-            )
-            .title_bottom(Line::from(" ? for help ").right_aligned()),
-        main_area,
+    let total = rows.len();
+    if tui_state.filter.is_prompting {
+        frame.render_stateful_widget(list, main_area, &mut tui_state.filter.cursor);
+    } else {
+        frame.render_stateful_widget(list, main_area, &mut tui_state.cursor);
+        tui_state.main_area_height = main_area.height;
+    }
+    let mut footer = Block::bordered().title_bottom(
+        Line::from(format!(" {}/{} ", cursor.map_or(0, |i| i + 1), total)).left_aligned(),
     );
-    frame.render_widget(Block::default(), sub_area);
+    if tui_state.is_reading {
+        footer = footer.title_bottom(
+            Line::from(format!(
+                " {} reading… ",
+                SPINNER_FRAMES[tui_state.spinner_frame]
+            ))
+            .centered(),
+        );
+    }
+    let help_key = tui_state.keymap.label(Action::ToggleHelp);
+    if !help_key.is_empty() {
+        footer = footer.title_bottom(Line::from(format!(" {help_key} for help ")).right_aligned());
+    }
+    frame.render_widget(footer, main_area);
+    if let Some(command) = &tui_state.command_prompt {
+        render_command_prompt(frame, command, sub_area);
+    } else {
+        render_filter_prompt(frame, &tui_state.filter, sub_area);
+    }
+    if tui_state.preview.is_showing {
+        render_preview(frame, tui_state, preview_area);
+    }
     if tui_state.is_showing_help {
-        render_help_message(frame);
+        render_help_message(frame, &tui_state.keymap);
+    }
+}
+
+/// Splits `text` into spans, emphasizing the chars at `positions`.
+fn highlight<'a>(text: &'a str, positions: &[usize], color: Color) -> Vec<Span<'a>> {
+    if positions.is_empty() {
+        return vec![Span::raw(printable(text))];
     }
+    let highlighted = Style::default().fg(color).bold();
+    let mut spans = vec![];
+    let mut start = 0;
+    let mut is_match = false;
+    for (n, (byte_idx, _)) in text.char_indices().enumerate() {
+        let matched = positions.binary_search(&n).is_ok();
+        if matched != is_match {
+            if byte_idx > start {
+                let span = Span::raw(printable(&text[start..byte_idx]));
+                spans.push(if is_match {
+                    span.style(highlighted)
+                } else {
+                    span
+                });
+            }
+            start = byte_idx;
+            is_match = matched;
+        }
+    }
+    let span = Span::raw(printable(&text[start..]));
+    spans.push(if is_match {
+        span.style(highlighted)
+    } else {
+        span
+    });
+    spans
 }
 
-fn render_help_message(frame: &mut Frame) {
+/// `source` indented under its candidate and cut down to `width` columns around the path, which is
+/// emphasized. The rest keeps the colors it was printed with, or is dimmed.
+fn source_line(source: &Source, width: usize) -> Line<'_> {
+    let text = &source.text;
+    let indentation = text.len() - text.trim_start().len();
+    let chars: Vec<(usize, char)> = text[indentation..]
+        .char_indices()
+        .map(|(i, c)| (i + indentation, c))
+        .collect();
+    let char_position = |byte: usize| {
+        chars
+            .iter()
+            .position(|&(i, _)| i >= byte)
+            .unwrap_or(chars.len())
+    };
+    let (path_start, path_end) = (
+        char_position(source.span.start),
+        char_position(source.span.end),
+    );
+    // Which chars are shown, and whether they're preceded and followed by an ellipsis.
+    let (from, to) = if chars.len() <= width {
+        (0, chars.len())
+    } else if path_end < width {
+        (0, width.saturating_sub(1))
+    } else {
+        let from = path_start
+            .saturating_sub(SOURCE_CONTEXT)
+            .min(chars.len() + 1 - width);
+        if chars.len() - from < width {
+            (from, chars.len())
+        } else {
+            (from, from + width.saturating_sub(2))
+        }
+    };
+
+    let mut spans = vec![Span::raw(SOURCE_INDENT)];
+    if from > 0 {
+        spans.push(Span::raw("…").dim());
+    }
+    let mut run = String::new();
+    let mut run_style = None;
+    for (n, &(byte, c)) in chars.iter().enumerate().take(to).skip(from) {
+        let mut style = source
+            .styles
+            .iter()
+            .find(|(range, _)| range.contains(&byte))
+            .map_or(Style::default().dim(), |&(_, style)| style);
+        if (path_start..path_end).contains(&n) {
+            style = style
+                .not_dim()
+                .add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        }
+        if run_style.is_some_and(|s| s != style) {
+            spans.push(Span::styled(
+                printable(&run).into_owned(),
+                run_style.unwrap(),
+            ));
+            run.clear();
+        }
+        run_style = Some(style);
+        run.push(if c == '\t' { ' ' } else { c });
+    }
+    if let Some(style) = run_style {
+        spans.push(Span::styled(printable(&run).into_owned(), style));
+    }
+    if to < chars.len() {
+        spans.push(Span::raw("…").dim());
+    }
+    Line::from(spans)
+}
+
+/// `:line:column`, as much of it as is known, or `:line-end_line` for a range.
+fn position(result: &MatchResult) -> Option<String> {
+    let line = result.line_number?;
+    Some(match (result.column, result.end_line) {
+        (_, Some(end_line)) => format!(":{line}-{end_line}"),
+        (Some(column), None) => format!(":{line}:{column}"),
+        (None, None) => format!(":{line}"),
+    })
+}
+
+/// `text` as shown in the list, with control characters such as the newlines of NUL-delimited
+/// paths replaced by their symbols, one character for one.
+fn printable(text: &str) -> Cow<'_, str> {
+    let text = os_str::display(text);
+    if !text.chars().any(|c| c.is_ascii_control()) {
+        return text;
+    }
+    Cow::Owned(
+        text.chars()
+            .map(|c| match c {
+                '\x00'..='\x1f' => char::from_u32(0x2400 + c as u32).unwrap(),
+                '\x7f' => '\u{2421}',
+                c => c,
+            })
+            .collect(),
+    )
+}
+
+fn render_preview(frame: &mut Frame, tui_state: &mut TUIState, area: Rect) {
+    let Some(item) = tui_state.current_item() else {
+        frame.render_widget(Block::bordered(), area);
+        return;
+    };
+    let result = &tui_state.items[item].result;
+    let path = result.fs_path();
+    // The lines to highlight.
+    let marked = result
+        .line_number
+        .map(|line| line as usize..=result.end_line.unwrap_or(line) as usize);
+    let title = printable(&result.path);
+    let block = Block::bordered().title_top(Line::from(format!(" {title} ")).centered());
+    let height = block.inner(area).height;
+    if tui_state.preview.item != Some(item) {
+        tui_state.preview.item = Some(item);
+        tui_state.preview.scroll = None;
+    }
+    tui_state.preview.height = height;
+
+    let first_line = tui_state.preview_first_line(item);
+    let key = (item, first_line, height);
+    if tui_state.preview.cache.as_ref().map(|(k, _)| *k) != Some(key) {
+        let content = preview::load(&path, first_line, height as usize);
+        tui_state.preview.cache = Some((key, content));
+    }
+    let Some((_, content)) = &tui_state.preview.cache else {
+        return;
+    };
+
+    let lines: Vec<Line> = match content {
+        PreviewContent::Text { first_line, lines } => {
+            let gutter_width = (first_line + lines.len()).to_string().len();
+            lines
+                .iter()
+                .enumerate()
+                .map(|(i, text)| {
+                    let number = first_line + i + 1;
+                    let line = Line::from(vec![
+                        Span::raw(format!("{number:>gutter_width$} ")).dim(),
+                        Span::raw(text.as_str()),
+                    ]);
+                    if marked.as_ref().is_some_and(|m| m.contains(&number)) {
+                        line.style(Style::default().bg(tui_state.theme.preview_line))
+                    } else {
+                        line
+                    }
+                })
+                .collect()
+        }
+        PreviewContent::Binary => vec![Line::from("<binary file>").dim().centered()],
+        PreviewContent::Directory(entries) => entries
+            .iter()
+            .skip(first_line)
+            .take(height as usize)
+            .map(|e| Line::from(e.as_str()))
+            .collect(),
+        PreviewContent::Unavailable(reason) => {
+            vec![Line::from(format!("<{reason}>")).dim().centered()]
+        }
+    };
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn render_command_prompt(frame: &mut Frame, command: &str, area: Rect) {
+    let prompt = format!(":{command}");
+    frame.set_cursor_position((area.x + prompt.chars().count() as u16, area.y));
+    frame.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::raw(prompt),
+            Span::raw("  ($F = all files, {} = each file, {line} = its line number)").dim(),
+        ])),
+        area,
+    );
+}
+
+fn render_filter_prompt(frame: &mut Frame, filter: &Filter, area: Rect) {
+    if area.height == 0 {
+        return;
+    }
+    let prompt = format!("/{}", filter.query);
+    if filter.is_prompting {
+        frame.set_cursor_position((area.x + prompt.chars().count() as u16, area.y));
+        frame.render_widget(Paragraph::new(prompt), area);
+    } else {
+        frame.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::raw(prompt),
+                Span::raw(format!("  ({} matches, n/N to jump)", filter.matches.len())).dim(),
+            ])),
+            area,
+        );
+    }
+}
+
+fn render_help_message(frame: &mut Frame, keymap: &Keymap) {
     // Setup
     let popup_block = Block::bordered().title_top(Line::from(" Help ").centered());
     let popup_area = popup_area(frame.area(), 40, 80);
@@ -283,13 +1235,18 @@ fn render_help_message(frame: &mut Frame) {
     ])
     .areas(content_area);
 
-    let rows: [Rect; HELP_MESSAGE_ENTRIES.len()] = Layout::vertical(vec![
+    let rows = Layout::vertical(vec![
         Constraint::Length(1);
         // Constraint::Ratio(1, HELP_MESSAGE_ENTRIES.len() as u32);
         HELP_MESSAGE_ENTRIES.len()
     ])
-    .areas(text_area);
-    for (i, &(key, desc)) in HELP_MESSAGE_ENTRIES.iter().enumerate() {
+    .split(text_area);
+    for (i, &(actions, desc)) in HELP_MESSAGE_ENTRIES.iter().enumerate() {
+        let key = actions
+            .iter()
+            .map(|a| keymap.label(*a))
+            .collect::<Vec<_>>()
+            .join("/");
         let columns: [Rect; 2] =
             Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
                 .areas(rows[i]);
@@ -315,3 +1272,180 @@ fn popup_area(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
     let [area] = horizontal.areas(area);
     area
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::matcher::MatchResult;
+    use crate::pipe::{Candidate, Found};
+    use crate::tui::{PickerOptions, TUIState, handle_key, render};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use ratatui::{Terminal, backend::TestBackend};
+
+    fn receive(tui_state: &mut TUIState, path: &str) {
+        tui_state.receive(Found::Candidate(Candidate {
+            result: MatchResult {
+                path: String::from(path),
+                ..Default::default()
+            },
+            source: None,
+        }));
+        tui_state.refresh_filter();
+    }
+
+    fn receive_line(tui_state: &mut TUIState, path: &str, line_number: u32) {
+        tui_state.receive(Found::Candidate(Candidate {
+            result: MatchResult {
+                path: String::from(path),
+                line_number: Some(line_number),
+                ..Default::default()
+            },
+            source: None,
+        }));
+        tui_state.refresh_filter();
+    }
+
+    /// `path:line` of the item at `i` in the list.
+    fn line_at(tui_state: &TUIState, i: usize) -> String {
+        let result = &tui_state.items[i].result;
+        format!("{}:{}", result.path, result.line_number.unwrap())
+    }
+
+    fn with_candidates(paths: &[&str]) -> TUIState {
+        let mut tui_state = TUIState::new(PickerOptions::default());
+        for path in paths {
+            receive(&mut tui_state, path);
+        }
+        tui_state
+    }
+
+    fn press(tui_state: &mut TUIState, keys: &str) {
+        for c in keys.chars() {
+            handle_key(
+                tui_state,
+                KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+            );
+        }
+    }
+
+    fn press_code(tui_state: &mut TUIState, code: KeyCode) {
+        handle_key(tui_state, KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn groups_lines_under_their_path_as_they_come_in() {
+        let mut tui_state = TUIState::new(PickerOptions {
+            per_line: true,
+            ..PickerOptions::default()
+        });
+        receive_line(&mut tui_state, "a.rs", 1);
+        receive_line(&mut tui_state, "b.rs", 2);
+        press(&mut tui_state, "j p");
+        footer(&mut tui_state);
+        for (path, line_number) in [("a.rs", 3), ("c.rs", 4), ("b.rs", 5), ("a.rs", 6)] {
+            receive_line(&mut tui_state, path, line_number);
+            let cursor = tui_state.cursor.selected().unwrap();
+            assert_eq!(line_at(&tui_state, cursor), "b.rs:2");
+            assert_eq!(tui_state.preview.item, Some(cursor));
+            let selected: Vec<String> = tui_state
+                .selected()
+                .into_iter()
+                .map(|i| line_at(&tui_state, i))
+                .collect();
+            assert_eq!(selected, ["b.rs:2"]);
+        }
+        let listed: Vec<String> = (0..tui_state.items.len())
+            .map(|i| line_at(&tui_state, i))
+            .collect();
+        assert_eq!(
+            listed,
+            ["a.rs:1", "a.rs:3", "a.rs:6", "b.rs:2", "b.rs:5", "c.rs:4"]
+        );
+
+        press(&mut tui_state, "/b");
+        assert_eq!(ranked(&tui_state), ["b.rs", "b.rs"]);
+        receive_line(&mut tui_state, "a.rs", 7);
+        receive_line(&mut tui_state, "b.rs", 8);
+        let ranked_lines: Vec<String> = tui_state
+            .filter
+            .ranking
+            .iter()
+            .map(|&id| line_at(&tui_state, tui_state.position(id).unwrap()))
+            .collect();
+        assert_eq!(ranked_lines, ["b.rs:2", "b.rs:5", "b.rs:8"]);
+        press_code(&mut tui_state, KeyCode::Up);
+        press_code(&mut tui_state, KeyCode::Enter);
+        assert_eq!(
+            line_at(&tui_state, tui_state.cursor.selected().unwrap()),
+            "b.rs:8"
+        );
+    }
+
+    /// The paths listed while filtering, best match first.
+    fn ranked(tui_state: &TUIState) -> Vec<&str> {
+        let filter = &tui_state.filter;
+        filter
+            .ranking
+            .iter()
+            .map(|&id| {
+                let i = tui_state.position(id).unwrap();
+                tui_state.items[i].result.path.as_str()
+            })
+            .collect()
+    }
+
+    /// Draws the list and returns its bottom border, where the cursor position is shown.
+    fn footer(tui_state: &mut TUIState) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(40, 8)).unwrap();
+        terminal.draw(|frame| render(frame, tui_state)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.width)
+            .map(|x| buffer[(x, buffer.area.height - 1)].symbol())
+            .collect()
+    }
+
+    #[test]
+    fn filters_candidates_and_moves_the_cursor_to_the_chosen_one() {
+        let mut tui_state = with_candidates(&["src/main.rs", "README.md", "src/tui.rs"]);
+        press(&mut tui_state, "/srctui");
+        assert_eq!(ranked(&tui_state), ["src/tui.rs"]);
+        for _ in 0..3 {
+            press_code(&mut tui_state, KeyCode::Backspace);
+        }
+        assert_eq!(ranked(&tui_state), ["src/main.rs", "src/tui.rs"]);
+        assert_eq!(tui_state.filter.cursor.selected(), Some(1));
+        press_code(&mut tui_state, KeyCode::Enter);
+        assert!(!tui_state.filter.is_prompting);
+        assert_eq!(tui_state.cursor.selected(), Some(2));
+    }
+
+    #[test]
+    fn keeps_the_filter_cursor_on_its_item_as_candidates_come_in() {
+        let mut tui_state = with_candidates(&["lib/parse.rs", "src/parser/mod.rs"]);
+        press(&mut tui_state, "/parse");
+        press_code(&mut tui_state, KeyCode::Down);
+        assert_eq!(ranked(&tui_state)[1], "src/parser/mod.rs");
+        receive(&mut tui_state, "parse.rs");
+        receive(&mut tui_state, "unrelated.txt");
+        assert_eq!(ranked(&tui_state).len(), 3);
+        let cursor = tui_state.filter.cursor.selected().unwrap();
+        assert_eq!(ranked(&tui_state)[cursor], "src/parser/mod.rs");
+    }
+
+    #[test]
+    fn wraps_around_the_ends_of_the_list() {
+        let mut tui_state = with_candidates(&["a.rs", "b.rs", "c.rs"]);
+        press(&mut tui_state, "k");
+        assert_eq!(tui_state.cursor.selected(), Some(2));
+        assert!(footer(&mut tui_state).contains(" 3/3 "));
+        press(&mut tui_state, "j");
+        assert_eq!(tui_state.cursor.selected(), Some(0));
+        press(&mut tui_state, "G");
+        assert!(footer(&mut tui_state).contains(" 3/3 "));
+
+        press(&mut tui_state, "/");
+        press_code(&mut tui_state, KeyCode::Up);
+        assert_eq!(tui_state.filter.cursor.selected(), Some(2));
+        press_code(&mut tui_state, KeyCode::Down);
+        assert_eq!(tui_state.filter.cursor.selected(), Some(0));
+    }
+}